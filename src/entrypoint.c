@@ -1,3 +1,5 @@
+#include <R_ext/Rdynload.h>
+
 // We need to forward routine registration from C to Rust
 // to avoid the linker removing the static library.
 
@@ -5,4 +7,7 @@ void R_init_rsgeo_extendr(void *dll);
 
 void R_init_rsgeo(void *dll) {
     R_init_rsgeo_extendr(dll);
+    // R_registerRoutines() would replace the routines registered by extendr
+    // so the C routines in wk.c are found by name instead
+    R_useDynamicSymbols((DllInfo *) dll, TRUE);
 }
@@ -233,6 +233,51 @@ fn geom_line(x: List, y: List) -> Robj {
     as_rsgeo_vctr(res, "linestring")
 }
 
+#[extendr]
+fn encode_polyline_(x: List, precision: i32) -> Strings {
+    if !x.inherits("rs_LINESTRING") {
+        panic!("`x` must be of class `rs_LINESTRING`")
+    }
+
+    // coordinates outside of lon/lat bounds cannot be encoded
+    let res_vec = x
+        .iter()
+        .map(|(_, xi)| {
+            if xi.is_null() {
+                Rstr::na()
+            } else {
+                let l = LineString::from(Geom::from(xi));
+                match polyline::encode_coordinates(l, precision as u32) {
+                    Ok(enc) => Rstr::from(enc),
+                    Err(_) => Rstr::na(),
+                }
+            }
+        })
+        .collect::<Vec<Rstr>>();
+
+    Strings::from_values(res_vec)
+}
+
+#[extendr]
+fn decode_polyline_(x: Strings, precision: i32) -> Robj {
+    // invalid polylines are returned as missing
+    let res_vec = x
+        .iter()
+        .map(|xi| {
+            if xi.is_na() {
+                NULL.into_robj()
+            } else {
+                match polyline::decode_polyline(xi.as_str(), precision as u32) {
+                    Ok(l) => l.into_geom().into_robj(),
+                    Err(_) => NULL.into_robj(),
+                }
+            }
+        })
+        .collect::<Vec<Robj>>();
+
+    as_rsgeo_vctr(List::from_values(res_vec), "linestring")
+}
+
 extendr_module! {
     mod construction;
     fn geom_point_;
@@ -240,4 +285,6 @@ extendr_module! {
     fn geom_linestring_;
     fn geom_polygon_;
     fn geom_line;
+    fn encode_polyline_;
+    fn decode_polyline_;
 }
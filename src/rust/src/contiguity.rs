@@ -0,0 +1,159 @@
+use extendr_api::prelude::*;
+use geo_types::{Coord, Geometry, LineString, Polygon};
+use rayon::prelude::*;
+use sfconversions::geometry_from_list;
+use std::collections::HashMap;
+
+// a vertex of a ring along with the vertices before and after it
+struct Vertex {
+    id: usize,
+    coord: Coord,
+    prev: Coord,
+    next: Coord,
+}
+
+// Vertices are hashed by the grid cell they fall in. Without a snap tolerance
+// the cell is the exact coordinate.
+struct VertexIndex {
+    snap: f64,
+    cells: HashMap<(i64, i64), Vec<Vertex>>,
+}
+
+impl VertexIndex {
+    fn cell(&self, x: &Coord) -> (i64, i64) {
+        if self.snap > 0.0 {
+            (
+                (x.x / self.snap).floor() as i64,
+                (x.y / self.snap).floor() as i64,
+            )
+        } else {
+            // -0.0 and 0.0 are the same coordinate
+            let bits = |v: f64| if v == 0.0 { 0 } else { v.to_bits() as i64 };
+            (bits(x.x), bits(x.y))
+        }
+    }
+
+    fn same(&self, a: &Coord, b: &Coord) -> bool {
+        if self.snap > 0.0 {
+            (a.x - b.x).hypot(a.y - b.y) <= self.snap
+        } else {
+            a == b
+        }
+    }
+
+    fn insert(&mut self, v: Vertex) {
+        let key = self.cell(&v.coord);
+        self.cells.entry(key).or_default().push(v);
+    }
+
+    // vertices within the snap tolerance of `x`. A tolerance no larger than
+    // the cell size means only adjacent cells need to be searched
+    fn near<'a>(&'a self, x: &'a Coord) -> impl Iterator<Item = &'a Vertex> + 'a {
+        let (cx, cy) = self.cell(x);
+        let offsets: &[i64] = if self.snap > 0.0 { &[-1, 0, 1] } else { &[0] };
+
+        offsets
+            .iter()
+            .flat_map(move |dx| offsets.iter().map(move |dy| (cx + dx, cy + dy)))
+            .filter_map(|key| self.cells.get(&key))
+            .flatten()
+            .filter(move |v| self.same(&v.coord, x))
+    }
+}
+
+fn polygons(x: &Geometry) -> Vec<&Polygon> {
+    match x {
+        Geometry::Polygon(p) => vec![p],
+        Geometry::MultiPolygon(mp) => mp.0.iter().collect(),
+        _ => vec![],
+    }
+}
+
+// every vertex of a ring without the repeated closing coordinate
+fn ring_vertices(id: usize, x: &LineString) -> Vec<Vertex> {
+    let coords = &x.0;
+    let n = if x.is_closed() && coords.len() > 1 {
+        coords.len() - 1
+    } else {
+        coords.len()
+    };
+
+    (0..n)
+        .map(|i| Vertex {
+            id,
+            coord: coords[i],
+            prev: coords[(i + n - 1) % n],
+            next: coords[(i + 1) % n],
+        })
+        .collect()
+}
+
+fn vertices(id: usize, x: &Geometry) -> Vec<Vertex> {
+    polygons(x)
+        .into_iter()
+        .flat_map(|p| std::iter::once(p.exterior()).chain(p.interiors()))
+        .flat_map(|r| ring_vertices(id, r))
+        .collect()
+}
+
+#[extendr]
+fn contiguity_(x: List, rook: bool, snap: f64) -> List {
+    if !(x.inherits("rs_POLYGON") || x.inherits("rs_MULTIPOLYGON")) {
+        panic!("`x` must be of class `rs_POLYGON` or `rs_MULTIPOLYGON`")
+    }
+
+    if snap.is_nan() || snap < 0.0 {
+        panic!("`snap` must be a non-negative number")
+    }
+
+    let geoms = geometry_from_list(x);
+
+    let mut index = VertexIndex {
+        snap,
+        cells: HashMap::new(),
+    };
+
+    geoms
+        .iter()
+        .enumerate()
+        .filter_map(|(i, g)| g.as_ref().map(|g| vertices(i, g)))
+        .flatten()
+        .for_each(|v| index.insert(v));
+
+    // queen neighbors share a vertex. Rook neighbors also share the next
+    // vertex along the ring in either direction which makes a shared edge
+    let res = geoms
+        .par_iter()
+        .enumerate()
+        .map(|(i, g)| {
+            let Some(g) = g else {
+                return vec![];
+            };
+
+            let mut nbs = vertices(i, g)
+                .iter()
+                .flat_map(|v| {
+                    index
+                        .near(&v.coord)
+                        .filter(|w| w.id != i)
+                        .filter(|w| {
+                            !rook || index.same(&w.next, &v.next) || index.same(&w.prev, &v.next)
+                        })
+                        .map(|w| (w.id as i32) + 1)
+                        .collect::<Vec<i32>>()
+                })
+                .collect::<Vec<i32>>();
+
+            nbs.sort_unstable();
+            nbs.dedup();
+            nbs
+        })
+        .collect::<Vec<Vec<i32>>>();
+
+    List::from_values(res)
+}
+
+extendr_module! {
+    mod contiguity;
+    fn contiguity_;
+}
@@ -0,0 +1,124 @@
+use extendr_api::prelude::*;
+use geo_types::{Geometry, Point, Polygon};
+use rayon::prelude::*;
+use sfconversions::{geometry_from_list, vctrs::as_rsgeo_vctr, Geom};
+
+#[extendr]
+fn geohash_encode_(x: List, precision: i32) -> Strings {
+    if !x.inherits("rs_POINT") {
+        panic!("`x` must be of class `rs_POINT`")
+    }
+
+    // points outside of lon/lat bounds cannot be encoded
+    let res_vec = geometry_from_list(x)
+        .into_par_iter()
+        .map(|xi| match xi {
+            Some(Geometry::Point(p)) => geohash::encode(p.0, precision as usize).ok(),
+            _ => None,
+        })
+        .collect::<Vec<Option<String>>>();
+
+    let res_vec = res_vec
+        .into_iter()
+        .map(|xi| match xi {
+            Some(s) => Rstr::from(s),
+            None => Rstr::na(),
+        })
+        .collect::<Vec<Rstr>>();
+
+    Strings::from_values(res_vec)
+}
+
+// Strings can't be shared across threads so they are copied first
+fn hashes_from_strings(x: Strings) -> Vec<Option<String>> {
+    x.iter()
+        .map(|xi| {
+            if xi.is_na() {
+                None
+            } else {
+                Some(xi.as_str().to_owned())
+            }
+        })
+        .collect()
+}
+
+#[extendr]
+fn geohash_decode_(x: Strings) -> Robj {
+    let res_vec = hashes_from_strings(x)
+        .into_par_iter()
+        .map(|xi| {
+            xi.and_then(|h| geohash::decode(&h).ok())
+                .map(|(c, _, _)| Geometry::Point(Point(c)))
+        })
+        .collect::<Vec<Option<Geometry>>>();
+
+    let res_vec = res_vec
+        .into_iter()
+        .map(|xi| match xi {
+            Some(g) => Geom::from(g).into_robj(),
+            None => NULL.into_robj(),
+        })
+        .collect::<Vec<Robj>>();
+
+    as_rsgeo_vctr(List::from_values(res_vec), "point")
+}
+
+#[extendr]
+fn geohash_bounds_(x: Strings) -> Robj {
+    let res_vec = hashes_from_strings(x)
+        .into_par_iter()
+        .map(|xi| {
+            xi.and_then(|h| geohash::decode_bbox(&h).ok())
+                .map(|r| Geometry::Polygon(Polygon::from(r)))
+        })
+        .collect::<Vec<Option<Geometry>>>();
+
+    let res_vec = res_vec
+        .into_iter()
+        .map(|xi| match xi {
+            Some(g) => Geom::from(g).into_robj(),
+            None => NULL.into_robj(),
+        })
+        .collect::<Vec<Robj>>();
+
+    as_rsgeo_vctr(List::from_values(res_vec), "polygon")
+}
+
+#[extendr]
+fn geohash_neighbors_(x: Strings) -> List {
+    let res_vec = hashes_from_strings(x)
+        .into_par_iter()
+        .map(|xi| xi.and_then(|h| geohash::neighbors(&h).ok()))
+        .collect::<Vec<Option<geohash::Neighbors>>>();
+
+    // one character vector for each direction clockwise from north
+    let column = |f: fn(&geohash::Neighbors) -> &String| {
+        let col = res_vec
+            .iter()
+            .map(|xi| match xi {
+                Some(nb) => Rstr::from(f(nb).as_str()),
+                None => Rstr::na(),
+            })
+            .collect::<Vec<Rstr>>();
+        Strings::from_values(col)
+    };
+
+    list!(
+        n = column(|nb| &nb.n),
+        ne = column(|nb| &nb.ne),
+        e = column(|nb| &nb.e),
+        se = column(|nb| &nb.se),
+        s = column(|nb| &nb.s),
+        sw = column(|nb| &nb.sw),
+        w = column(|nb| &nb.w),
+        nw = column(|nb| &nb.nw)
+    )
+}
+
+extendr_module! {
+    mod geohashimpl;
+    fn geohash_encode_;
+    fn geohash_decode_;
+    fn geohash_bounds_;
+    fn geohash_neighbors_;
+}
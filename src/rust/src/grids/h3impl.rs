@@ -0,0 +1,205 @@
+use extendr_api::prelude::*;
+use geo::CoordsIter;
+use geo_types::{Coord, Geometry, Polygon};
+use h3o::geom::{ContainmentMode, TilerBuilder};
+use h3o::{CellIndex, LatLng, Resolution};
+use rayon::prelude::*;
+use sfconversions::{geometry_from_list, vctrs::as_rsgeo_vctr, Geom};
+
+fn as_resolution(x: i32) -> Resolution {
+    u8::try_from(x)
+        .ok()
+        .and_then(|r| Resolution::try_from(r).ok())
+        .unwrap_or_else(|| panic!("`resolution` must be between 0 and 15"))
+}
+
+// h3o accepts any finite coordinate so longitude and latitude are checked
+fn is_lonlat(x: Coord) -> bool {
+    (-180.0..=180.0).contains(&x.x) && (-90.0..=90.0).contains(&x.y)
+}
+
+// invalid cell indexes are treated as missing
+fn cells_from_strings(x: Strings) -> Vec<Option<CellIndex>> {
+    x.iter()
+        .map(|xi| {
+            if xi.is_na() {
+                None
+            } else {
+                xi.as_str().parse::<CellIndex>().ok()
+            }
+        })
+        .collect()
+}
+
+fn cells_to_strings(x: Vec<Option<CellIndex>>) -> Strings {
+    let res_vec = x
+        .into_iter()
+        .map(|xi| match xi {
+            Some(cell) => Rstr::from(cell.to_string()),
+            None => Rstr::na(),
+        })
+        .collect::<Vec<Rstr>>();
+
+    Strings::from_values(res_vec)
+}
+
+fn cell_sets_to_list(x: Vec<Vec<CellIndex>>) -> List {
+    let res_vec = x
+        .into_iter()
+        .map(|cells| cells_to_strings(cells.into_iter().map(Some).collect()))
+        .collect::<Vec<Strings>>();
+
+    List::from_values(res_vec)
+}
+
+#[extendr]
+fn h3_from_points_(x: List, resolution: i32) -> Strings {
+    if !x.inherits("rs_POINT") {
+        panic!("`x` must be of class `rs_POINT`")
+    }
+
+    let res = as_resolution(resolution);
+
+    let res_vec = geometry_from_list(x)
+        .into_par_iter()
+        .map(|xi| match xi {
+            Some(Geometry::Point(p)) if is_lonlat(p.0) => {
+                LatLng::new(p.y(), p.x()).ok().map(|ll| ll.to_cell(res))
+            }
+            _ => None,
+        })
+        .collect::<Vec<Option<CellIndex>>>();
+
+    cells_to_strings(res_vec)
+}
+
+#[extendr]
+fn h3_to_polygons_(x: Strings) -> Robj {
+    let res_vec = cells_from_strings(x)
+        .into_par_iter()
+        .map(|xi| xi.map(Polygon::from))
+        .collect::<Vec<Option<Polygon>>>();
+
+    let res_vec = res_vec
+        .into_iter()
+        .map(|xi| match xi {
+            Some(p) => Geom::from(p).into_robj(),
+            None => NULL.into_robj(),
+        })
+        .collect::<Vec<Robj>>();
+
+    as_rsgeo_vctr(List::from_values(res_vec), "polygon")
+}
+
+#[extendr]
+fn h3_polyfill_(x: List, resolution: i32, containment: &str) -> List {
+    if !(x.inherits("rs_POLYGON") || x.inherits("rs_MULTIPOLYGON")) {
+        panic!("`x` must be of class `rs_POLYGON` or `rs_MULTIPOLYGON`")
+    }
+
+    let res = as_resolution(resolution);
+
+    let mode = match containment {
+        "centroid" => ContainmentMode::ContainsCentroid,
+        "contains" => ContainmentMode::ContainsBoundary,
+        "intersects" => ContainmentMode::IntersectsBoundary,
+        "covers" => ContainmentMode::Covers,
+        _ => panic!("unknown containment mode `{containment}`"),
+    };
+
+    let res_vec = geometry_from_list(x)
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, xi)| {
+            let polys = match xi {
+                Some(Geometry::Polygon(p)) => vec![p],
+                Some(Geometry::MultiPolygon(mp)) => mp.0,
+                _ => vec![],
+            };
+
+            if !polys.iter().all(|p| p.coords_iter().all(is_lonlat)) {
+                return vec![];
+            }
+
+            let mut tiler = TilerBuilder::new(res).containment_mode(mode).build();
+
+            tiler
+                .add_batch(polys)
+                .unwrap_or_else(|e| panic!("invalid polygon at element {}: {e}", i + 1));
+
+            let mut cells = tiler.into_coverage().collect::<Vec<CellIndex>>();
+            cells.sort_unstable();
+            cells
+        })
+        .collect::<Vec<Vec<CellIndex>>>();
+
+    cell_sets_to_list(res_vec)
+}
+
+#[extendr]
+fn h3_disk_(x: Strings, k: i32) -> List {
+    if k < 0 {
+        panic!("`k` must be a positive integer")
+    }
+
+    let res_vec = cells_from_strings(x)
+        .into_par_iter()
+        .map(|xi| match xi {
+            Some(cell) => cell.grid_disk::<Vec<CellIndex>>(k as u32),
+            None => vec![],
+        })
+        .collect::<Vec<Vec<CellIndex>>>();
+
+    cell_sets_to_list(res_vec)
+}
+
+#[extendr]
+fn h3_parent_(x: Strings, resolution: i32) -> Strings {
+    let res = as_resolution(resolution);
+
+    let res_vec = cells_from_strings(x)
+        .into_par_iter()
+        .map(|xi| xi.and_then(|cell| cell.parent(res)))
+        .collect::<Vec<Option<CellIndex>>>();
+
+    cells_to_strings(res_vec)
+}
+
+#[extendr]
+fn h3_children_(x: Strings, resolution: i32) -> List {
+    let res = as_resolution(resolution);
+
+    let res_vec = cells_from_strings(x)
+        .into_par_iter()
+        .map(|xi| match xi {
+            Some(cell) => cell.children(res).collect(),
+            None => vec![],
+        })
+        .collect::<Vec<Vec<CellIndex>>>();
+
+    cell_sets_to_list(res_vec)
+}
+
+#[extendr]
+fn h3_resolution_(x: Strings) -> Integers {
+    let res_vec = cells_from_strings(x)
+        .into_iter()
+        .map(|xi| match xi {
+            Some(cell) => Rint::from(u8::from(cell.resolution()) as i32),
+            None => Rint::na(),
+        })
+        .collect::<Vec<Rint>>();
+
+    Integers::from_values(res_vec)
+}
+
+extendr_module! {
+    mod h3impl;
+    fn h3_from_points_;
+    fn h3_to_polygons_;
+    fn h3_polyfill_;
+    fn h3_disk_;
+    fn h3_parent_;
+    fn h3_children_;
+    fn h3_resolution_;
+}
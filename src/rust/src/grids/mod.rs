@@ -0,0 +1,12 @@
+use extendr_api::prelude::*;
+
+pub mod geohashimpl;
+pub mod h3impl;
+pub mod tileimpl;
+
+extendr_module! {
+    mod grids;
+    use geohashimpl;
+    use h3impl;
+    use tileimpl;
+}
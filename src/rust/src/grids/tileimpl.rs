@@ -0,0 +1,280 @@
+use extendr_api::prelude::*;
+use geo::{BooleanOps, Intersects};
+use geo_types::{
+    coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon,
+    Polygon, Rect,
+};
+use rayon::prelude::*;
+use sfconversions::{geometry_from_list, vctrs::as_rsgeo_vctr, Geom};
+use std::f64::consts::PI;
+
+use crate::utils::geoms_to_rsgeo;
+
+// Web Mercator cannot represent the poles
+const MAX_LAT: f64 = 85.051_128_779_806_59;
+
+fn to_integers(x: impl Iterator<Item = Option<i32>>) -> Integers {
+    Integers::from_values(x.map(|xi| match xi {
+        Some(v) => Rint::from(v),
+        None => Rint::na(),
+    }))
+}
+
+/// Fractional tile coordinates of a longitude and latitude.
+pub fn lonlat_to_tile(lon: f64, lat: f64, z: i32) -> (f64, f64) {
+    let n = 2_f64.powi(z);
+    let lat = lat.clamp(-MAX_LAT, MAX_LAT).to_radians();
+    let x = (lon + 180.0) / 360.0 * n;
+    let y = (1.0 - lat.tan().asinh() / PI) / 2.0 * n;
+    (x, y)
+}
+
+// longitude and latitude of fractional tile coordinates
+fn tile_to_lonlat(x: f64, y: f64, z: i32) -> (f64, f64) {
+    let n = 2_f64.powi(z);
+    let lon = x / n * 360.0 - 180.0;
+    let lat = (PI * (1.0 - 2.0 * y / n)).sinh().atan().to_degrees();
+    (lon, lat)
+}
+
+/// The envelope of a tile in longitude and latitude. `buffer` is a fraction
+/// of the tile's width that the envelope is expanded by on each side.
+pub fn tile_rect(x: i32, y: i32, z: i32, buffer: f64) -> Rect {
+    let (xmin, ymax) = tile_to_lonlat(x as f64 - buffer, y as f64 - buffer, z);
+    let (xmax, ymin) = tile_to_lonlat(x as f64 + 1.0 + buffer, y as f64 + 1.0 + buffer, z);
+    Rect::new(coord! { x: xmin, y: ymin }, coord! { x: xmax, y: ymax })
+}
+
+/// Clip a geometry to a rectangle. Returns `None` if nothing remains.
+pub fn clip_to_rect(x: &Geometry, rect: &Rect) -> Option<Geometry> {
+    if !x.intersects(rect) {
+        return None;
+    }
+
+    let env = rect.to_polygon();
+
+    let res = match x {
+        Geometry::Point(p) => Geometry::Point(*p),
+        Geometry::MultiPoint(mp) => Geometry::MultiPoint(MultiPoint::new(
+            mp.0.iter()
+                .filter(|p| p.intersects(rect))
+                .cloned()
+                .collect(),
+        )),
+        Geometry::Line(l) => {
+            let ml = MultiLineString::new(vec![LineString::from(*l)]);
+            Geometry::MultiLineString(env.clip(&ml, false))
+        }
+        Geometry::LineString(l) => {
+            let ml = MultiLineString::new(vec![l.clone()]);
+            Geometry::MultiLineString(env.clip(&ml, false))
+        }
+        Geometry::MultiLineString(ml) => Geometry::MultiLineString(env.clip(ml, false)),
+        Geometry::Polygon(p) => Geometry::MultiPolygon(env.intersection(p)),
+        Geometry::Rect(r) => Geometry::MultiPolygon(env.intersection(&r.to_polygon())),
+        Geometry::Triangle(t) => Geometry::MultiPolygon(env.intersection(&t.to_polygon())),
+        Geometry::MultiPolygon(mp) => {
+            Geometry::MultiPolygon(MultiPolygon::new(vec![env]).intersection(mp))
+        }
+        Geometry::GeometryCollection(gc) => Geometry::GeometryCollection(GeometryCollection(
+            gc.0.iter().filter_map(|g| clip_to_rect(g, rect)).collect(),
+        )),
+    };
+
+    let is_empty = match &res {
+        Geometry::MultiPoint(mp) => mp.0.is_empty(),
+        Geometry::MultiLineString(ml) => ml.0.is_empty(),
+        Geometry::MultiPolygon(mp) => mp.0.is_empty(),
+        Geometry::GeometryCollection(gc) => gc.0.is_empty(),
+        _ => false,
+    };
+
+    if is_empty {
+        None
+    } else {
+        Some(res)
+    }
+}
+
+#[extendr]
+fn tile_from_points_(x: List, zoom: i32) -> List {
+    if !x.inherits("rs_POINT") {
+        panic!("`x` must be of class `rs_POINT`")
+    }
+
+    let n = 2_f64.powi(zoom);
+
+    let res_vec = geometry_from_list(x)
+        .into_par_iter()
+        .map(|xi| match xi {
+            Some(Geometry::Point(p)) if p.x().is_finite() && p.y().is_finite() => {
+                let (tx, ty) = lonlat_to_tile(p.x(), p.y(), zoom);
+                let tx = tx.floor().clamp(0.0, n - 1.0) as i32;
+                let ty = ty.floor().clamp(0.0, n - 1.0) as i32;
+                (Some(tx), Some(ty))
+            }
+            _ => (None, None),
+        })
+        .collect::<Vec<(Option<i32>, Option<i32>)>>();
+
+    let (tx, ty): (Vec<Option<i32>>, Vec<Option<i32>>) = res_vec.into_iter().unzip();
+    let tz = tx
+        .iter()
+        .map(|xi| xi.map(|_| zoom))
+        .collect::<Vec<Option<i32>>>();
+
+    list!(
+        x = to_integers(tx.into_iter()),
+        y = to_integers(ty.into_iter()),
+        z = to_integers(tz.into_iter())
+    )
+}
+
+// tiles are recycled to a common length in R
+fn check_tile_lengths(x: &Integers, y: &Integers, z: &Integers) {
+    if x.len() != y.len() || x.len() != z.len() {
+        panic!("`x`, `y`, and `z` must be the same length")
+    }
+}
+
+#[extendr]
+fn tile_quadkey_(x: Integers, y: Integers, z: Integers) -> Strings {
+    check_tile_lengths(&x, &y, &z);
+
+    let res_vec = x
+        .iter()
+        .zip(y.iter())
+        .zip(z.iter())
+        .map(|((xi, yi), zi)| {
+            if xi.is_na() || yi.is_na() || zi.is_na() {
+                return Rstr::na();
+            }
+
+            let (tx, ty, tz) = (xi.inner(), yi.inner(), zi.inner());
+
+            if !(0..=30).contains(&tz) {
+                return Rstr::na();
+            }
+
+            let qk = (1..=tz)
+                .rev()
+                .map(|i| {
+                    let mask = 1 << (i - 1);
+                    let digit = ((tx & mask) != 0) as u8 + 2 * ((ty & mask) != 0) as u8;
+                    (b'0' + digit) as char
+                })
+                .collect::<String>();
+
+            Rstr::from(qk)
+        })
+        .collect::<Vec<Rstr>>();
+
+    Strings::from_values(res_vec)
+}
+
+#[extendr]
+fn quadkey_to_tile_(x: Strings) -> List {
+    // invalid quadkeys are missing
+    let res_vec = x
+        .iter()
+        .map(|xi| {
+            if xi.is_na() {
+                return None;
+            }
+
+            let qk = xi.as_str();
+            let z = qk.len() as i32;
+
+            if z > 30 {
+                return None;
+            }
+
+            qk.chars()
+                .enumerate()
+                .try_fold((0, 0), |(tx, ty), (i, c)| {
+                    let mask = 1 << (z - i as i32 - 1);
+                    match c {
+                        '0' => Some((tx, ty)),
+                        '1' => Some((tx | mask, ty)),
+                        '2' => Some((tx, ty | mask)),
+                        '3' => Some((tx | mask, ty | mask)),
+                        _ => None,
+                    }
+                })
+                .map(|(tx, ty)| (tx, ty, z))
+        })
+        .collect::<Vec<Option<(i32, i32, i32)>>>();
+
+    list!(
+        x = to_integers(res_vec.iter().map(|t| t.map(|t| t.0))),
+        y = to_integers(res_vec.iter().map(|t| t.map(|t| t.1))),
+        z = to_integers(res_vec.iter().map(|t| t.map(|t| t.2)))
+    )
+}
+
+#[extendr]
+fn tile_bounds_(x: Integers, y: Integers, z: Integers, buffer: f64) -> Robj {
+    check_tile_lengths(&x, &y, &z);
+
+    let res_vec = x
+        .iter()
+        .zip(y.iter())
+        .zip(z.iter())
+        .map(|((xi, yi), zi)| {
+            if xi.is_na() || yi.is_na() || zi.is_na() {
+                NULL.into_robj()
+            } else {
+                let rect = tile_rect(xi.inner(), yi.inner(), zi.inner(), buffer);
+                Geom::from(Polygon::from(rect)).into_robj()
+            }
+        })
+        .collect::<Vec<Robj>>();
+
+    as_rsgeo_vctr(List::from_values(res_vec), "polygon")
+}
+
+#[extendr]
+fn clip_to_tile_(x: List, tx: Integers, ty: Integers, tz: Integers, buffer: f64) -> Robj {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    check_tile_lengths(&tx, &ty, &tz);
+
+    if tx.len() != x.len() {
+        panic!("the tiles must be the same length as `geoms`")
+    }
+
+    let rects = tx
+        .iter()
+        .zip(ty.iter())
+        .zip(tz.iter())
+        .map(|((xi, yi), zi)| {
+            if xi.is_na() || yi.is_na() || zi.is_na() {
+                None
+            } else {
+                Some(tile_rect(xi.inner(), yi.inner(), zi.inner(), buffer))
+            }
+        })
+        .collect::<Vec<Option<Rect>>>();
+
+    let res_vec = geometry_from_list(x)
+        .into_par_iter()
+        .zip(rects.into_par_iter())
+        .map(|(xi, rect)| match (xi, rect) {
+            (Some(g), Some(r)) => clip_to_rect(&g, &r),
+            _ => None,
+        })
+        .collect::<Vec<Option<Geometry>>>();
+
+    geoms_to_rsgeo(res_vec)
+}
+
+extendr_module! {
+    mod tileimpl;
+    fn tile_from_points_;
+    fn tile_quadkey_;
+    fn quadkey_to_tile_;
+    fn tile_bounds_;
+    fn clip_to_tile_;
+}
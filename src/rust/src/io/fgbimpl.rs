@@ -0,0 +1,234 @@
+use extendr_api::prelude::*;
+use flatgeobuf::{
+    ColumnType, FallibleStreamingIterator, FgbCrs, FgbReader, FgbWriter, FgbWriterOptions,
+    GeometryType,
+};
+use geojson::{JsonObject, JsonValue};
+use geozero::{ColumnValue, FeatureProperties, PropertyProcessor, ToGeo};
+use sfconversions::{geometry_from_list, vctrs::rsgeo_type};
+use std::fs::File;
+use std::io::{BufReader, BufWriter};
+
+use super::geojsonimpl::{process_properties_with_columns, ColType, PropColumn};
+use crate::utils::geoms_to_rsgeo;
+
+#[extendr]
+fn read_fgb_(file: &str, bbox: Doubles) -> List {
+    let f = File::open(file).unwrap_or_else(|e| panic!("unable to open `{file}`: {e}"));
+    let mut reader = BufReader::new(f);
+
+    let fgb = FgbReader::open(&mut reader)
+        .unwrap_or_else(|e| panic!("unable to read FlatGeobuf `{file}`: {e}"));
+
+    // columns are taken from the header so that they keep their order and
+    // type when values are missing, as missing values are not written
+    let columns = fgb
+        .header()
+        .columns()
+        .map(|cols| {
+            cols.iter()
+                .map(|c| (c.name().to_string(), column_type(c.type_())))
+                .collect::<Vec<(String, ColType)>>()
+        })
+        .unwrap_or_default();
+
+    // only features intersecting the bbox are decoded when using the index
+    let features = if bbox.len() == 4 {
+        fgb.select_bbox(
+            bbox[0].inner(),
+            bbox[1].inner(),
+            bbox[2].inner(),
+            bbox[3].inner(),
+        )
+    } else {
+        fgb.select_all()
+    };
+
+    let mut features = features.unwrap_or_else(|e| panic!("unable to read features: {e}"));
+
+    let mut geoms = Vec::new();
+    let mut props = Vec::new();
+
+    while let Some(feat) = features
+        .next()
+        .unwrap_or_else(|e| panic!("failed to read feature {}: {e}", geoms.len() + 1))
+    {
+        let geom = match feat.geometry() {
+            Some(_) => Some(
+                feat.to_geo()
+                    .unwrap_or_else(|e| panic!("invalid geometry in feature {}: {e}", geoms.len() + 1)),
+            ),
+            None => None,
+        };
+
+        let mut p = JsonProperties::default();
+        feat.process_properties(&mut p)
+            .unwrap_or_else(|e| panic!("invalid properties in feature {}: {e}", geoms.len() + 1));
+
+        geoms.push(geom);
+        props.push(Some(p.0));
+    }
+
+    list!(
+        geometry = geoms_to_rsgeo(geoms),
+        properties = process_properties_with_columns(props, columns)
+    )
+}
+
+fn column_type(x: ColumnType) -> ColType {
+    match x {
+        ColumnType::Bool => ColType::Logical,
+        ColumnType::Byte
+        | ColumnType::UByte
+        | ColumnType::Short
+        | ColumnType::UShort
+        | ColumnType::Int => ColType::Integer,
+        // larger integers do not fit in an R integer
+        ColumnType::UInt
+        | ColumnType::Long
+        | ColumnType::ULong
+        | ColumnType::Float
+        | ColumnType::Double => ColType::Double,
+        ColumnType::String | ColumnType::DateTime | ColumnType::Binary => ColType::Character,
+        // JSON values may be of any type so it is found from the values
+        _ => ColType::Null,
+    }
+}
+
+// Collects FlatGeobuf properties as JSON values so that they can be
+// converted to a data.frame the same way as GeoJSON properties
+#[derive(Default)]
+struct JsonProperties(JsonObject);
+
+impl PropertyProcessor for JsonProperties {
+    fn property(
+        &mut self,
+        _idx: usize,
+        name: &str,
+        value: &ColumnValue,
+    ) -> geozero::error::Result<bool> {
+        let value = match value {
+            ColumnValue::Byte(v) => JsonValue::from(*v),
+            ColumnValue::UByte(v) => JsonValue::from(*v),
+            ColumnValue::Bool(v) => JsonValue::from(*v),
+            ColumnValue::Short(v) => JsonValue::from(*v),
+            ColumnValue::UShort(v) => JsonValue::from(*v),
+            ColumnValue::Int(v) => JsonValue::from(*v),
+            ColumnValue::UInt(v) => JsonValue::from(*v),
+            ColumnValue::Long(v) => JsonValue::from(*v),
+            ColumnValue::ULong(v) => JsonValue::from(*v),
+            ColumnValue::Float(v) => JsonValue::from(*v as f64),
+            ColumnValue::Double(v) => JsonValue::from(*v),
+            ColumnValue::String(v) | ColumnValue::DateTime(v) => JsonValue::from(*v),
+            ColumnValue::Json(v) => {
+                serde_json::from_str(v).unwrap_or_else(|_| JsonValue::from(*v))
+            }
+            ColumnValue::Binary(v) => JsonValue::from(
+                v.iter().map(|b| format!("{b:02x}")).collect::<String>(),
+            ),
+        };
+
+        self.0.insert(name.to_string(), value);
+        Ok(false)
+    }
+}
+
+#[extendr]
+fn write_fgb_(x: List, properties: List, file: &str, crs: Integers, index: bool) {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let n = x.len();
+
+    let cols = properties
+        .iter()
+        .map(|(name, col)| (name.to_string(), PropColumn::from_robj(name, col)))
+        .collect::<Vec<(String, PropColumn)>>();
+
+    if cols.iter().any(|(_, col)| col.len() != n) {
+        panic!("`properties` must have the same number of rows as the length of `x`")
+    }
+
+    let geometry_type = match rsgeo_type(&x).as_str() {
+        "point" => GeometryType::Point,
+        "linestring" => GeometryType::LineString,
+        "polygon" => GeometryType::Polygon,
+        "multipoint" => GeometryType::MultiPoint,
+        "multilinestring" => GeometryType::MultiLineString,
+        "multipolygon" => GeometryType::MultiPolygon,
+        "geometrycollection" => GeometryType::GeometryCollection,
+        _ => GeometryType::Unknown,
+    };
+
+    let geoms = geometry_from_list(x);
+
+    if let Some(i) = geoms.iter().position(|g| g.is_none()) {
+        panic!("FlatGeobuf cannot store missing geometries, found at element {}", i + 1)
+    }
+
+    let crs = match crs.iter().next() {
+        Some(code) if !code.is_na() => FgbCrs {
+            code: code.inner(),
+            ..Default::default()
+        },
+        _ => FgbCrs::default(),
+    };
+
+    // geometries are written as is rather than promoted to multi types
+    let options = FgbWriterOptions {
+        write_index: index,
+        promote_to_multi: false,
+        crs,
+        ..Default::default()
+    };
+
+    let name = std::path::Path::new(file)
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or("");
+
+    let mut fgb = FgbWriter::create_with_options(name, geometry_type, options)
+        .unwrap_or_else(|e| panic!("unable to create FlatGeobuf: {e}"));
+
+    for (name, col) in cols.iter() {
+        let col_type = match col {
+            PropColumn::Logical(_) => ColumnType::Bool,
+            PropColumn::Integer(_) => ColumnType::Int,
+            PropColumn::Double(_) => ColumnType::Double,
+            PropColumn::Character(_) => ColumnType::String,
+        };
+
+        fgb.add_column(name, col_type, |_, col| col.nullable = true);
+    }
+
+    for (i, g) in geoms.into_iter().flatten().enumerate() {
+        fgb.add_feature_geom(g, |feat| {
+            for (j, (name, col)) in cols.iter().enumerate() {
+                // missing values are omitted and read as null
+                let value = match col {
+                    PropColumn::Logical(v) => v[i].map(ColumnValue::Bool),
+                    PropColumn::Integer(v) => v[i].map(ColumnValue::Int),
+                    PropColumn::Double(v) => v[i].map(ColumnValue::Double),
+                    PropColumn::Character(v) => v[i].as_deref().map(ColumnValue::String),
+                };
+
+                if let Some(value) = value {
+                    feat.property(j, name, &value).unwrap();
+                }
+            }
+        })
+        .unwrap_or_else(|e| panic!("failed to write feature {}: {e}", i + 1));
+    }
+
+    let out = File::create(file).unwrap_or_else(|e| panic!("unable to create `{file}`: {e}"));
+
+    fgb.write(&mut BufWriter::new(out))
+        .unwrap_or_else(|e| panic!("failed to write `{file}`: {e}"));
+}
+
+extendr_module! {
+    mod fgbimpl;
+    fn read_fgb_;
+    fn write_fgb_;
+}
@@ -0,0 +1,531 @@
+use arrow_array::cast::AsArray;
+use arrow_array::ffi::{from_ffi, FFI_ArrowArray, FFI_ArrowSchema};
+use arrow_array::types::Float64Type;
+use arrow_array::{
+    make_array, Array, ArrayRef, BinaryArray, FixedSizeListArray, Float64Array, ListArray,
+    StructArray,
+};
+use arrow_buffer::{NullBuffer, OffsetBuffer, ScalarBuffer};
+use arrow_schema::{DataType, Field, Fields};
+use extendr_api::prelude::*;
+use extendr_api::GetSexp;
+use geo_types::{
+    Coord, Geometry, LineString, MultiLineString, MultiPoint, MultiPolygon, Point, Polygon,
+};
+use libR_sys::R_ExternalPtrAddr;
+use sfconversions::{geometry_from_list, vctrs::rsgeo_type};
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+use wkt::TryFromWkt;
+
+use super::wkbimpl::{geometry_to_wkb, wkb_to_geometry};
+use crate::utils::geoms_to_rsgeo;
+
+// Import -----------------------------------------------------------------
+
+#[extendr]
+fn geoarrow_to_geoms_(array: Robj, schema: Robj) -> Robj {
+    let array_ptr = unsafe { R_ExternalPtrAddr(array.get()) as *mut FFI_ArrowArray };
+    let schema_ptr = unsafe { R_ExternalPtrAddr(schema.get()) as *const FFI_ArrowSchema };
+
+    if array_ptr.is_null() || schema_ptr.is_null() {
+        panic!("`x` is not a valid Arrow array")
+    }
+
+    // the schema is borrowed but the array is moved and released when dropped
+    let ffi_schema = unsafe { &*schema_ptr };
+    let field = Field::try_from(ffi_schema)
+        .unwrap_or_else(|e| panic!("failed to import Arrow schema: {e}"));
+
+    let ffi_array = unsafe { FFI_ArrowArray::from_raw(array_ptr) };
+    let data = unsafe { from_ffi(ffi_array, ffi_schema) }
+        .unwrap_or_else(|e| panic!("failed to import Arrow array: {e}"));
+
+    let arr = make_array(data);
+
+    let extension = match field.metadata().get("ARROW:extension:name") {
+        Some(ext) => ext.as_str(),
+        None => match field.data_type() {
+            DataType::Binary | DataType::LargeBinary => "geoarrow.wkb",
+            dt => panic!("`x` must have a GeoArrow extension type, found `{dt}`"),
+        },
+    };
+
+    geoms_to_rsgeo(geoarrow_to_geometries(arr.as_ref(), extension))
+}
+
+// Coordinates can be stored as a struct of x, y (separated) or as a
+// fixed size list of xy (interleaved). Only x and y are kept.
+enum Coords<'a> {
+    Separated(&'a Float64Array, &'a Float64Array),
+    Interleaved(&'a FixedSizeListArray, &'a Float64Array),
+}
+
+impl<'a> Coords<'a> {
+    fn new(x: &'a dyn Array) -> Self {
+        if let Some(s) = x.as_struct_opt() {
+            if s.num_columns() < 2 {
+                panic!("GeoArrow coordinates must have at least 2 dimensions")
+            }
+
+            let xs = s.column(0).as_primitive_opt::<Float64Type>();
+            let ys = s.column(1).as_primitive_opt::<Float64Type>();
+
+            match (xs, ys) {
+                (Some(xs), Some(ys)) => Coords::Separated(xs, ys),
+                _ => panic!("GeoArrow coordinates must be doubles"),
+            }
+        } else if let Some(fsl) = x.as_fixed_size_list_opt() {
+            let xy = fsl
+                .values()
+                .as_primitive_opt::<Float64Type>()
+                .expect("GeoArrow coordinates must be doubles");
+
+            Coords::Interleaved(fsl, xy)
+        } else {
+            panic!("unsupported GeoArrow coordinate type `{}`", x.data_type())
+        }
+    }
+
+    fn coord(&self, i: usize) -> Coord {
+        match self {
+            Coords::Separated(x, y) => Coord {
+                x: x.value(i),
+                y: y.value(i),
+            },
+            // the offset accounts for arrays that are a slice of their values
+            Coords::Interleaved(fsl, xy) => {
+                let j = fsl.value_offset(i) as usize;
+                Coord {
+                    x: xy.value(j),
+                    y: xy.value(j + 1),
+                }
+            }
+        }
+    }
+
+    fn linestring(&self, r: Range<usize>) -> LineString {
+        LineString::new(r.map(|i| self.coord(i)).collect())
+    }
+}
+
+fn as_list(x: &dyn Array) -> &ListArray {
+    x.as_list_opt::<i32>()
+        .unwrap_or_else(|| panic!("expected a GeoArrow list array, found `{}`", x.data_type()))
+}
+
+fn offsets(x: &ListArray, i: usize) -> Range<usize> {
+    let o = x.value_offsets();
+    o[i] as usize..o[i + 1] as usize
+}
+
+fn polygon(rings: &ListArray, coords: &Coords, r: Range<usize>) -> Polygon {
+    let mut rings = r.map(|j| coords.linestring(offsets(rings, j)));
+    let ext = rings.next().unwrap_or(LineString::new(vec![]));
+    Polygon::new(ext, rings.collect())
+}
+
+fn geoarrow_to_geometries(x: &dyn Array, extension: &str) -> Vec<Option<Geometry>> {
+    let n = x.len();
+
+    let res = match extension {
+        "geoarrow.point" => {
+            let coords = Coords::new(x);
+            (0..n)
+                .map(|i| {
+                    // empty points are stored as NaN
+                    let crd = coords.coord(i);
+                    if crd.x.is_nan() && crd.y.is_nan() {
+                        None
+                    } else {
+                        Some(Geometry::Point(Point(crd)))
+                    }
+                })
+                .collect::<Vec<_>>()
+        }
+        "geoarrow.linestring" => {
+            let lines = as_list(x);
+            let coords = Coords::new(lines.values().as_ref());
+            (0..n)
+                .map(|i| Some(Geometry::LineString(coords.linestring(offsets(lines, i)))))
+                .collect()
+        }
+        "geoarrow.polygon" => {
+            let polys = as_list(x);
+            let rings = as_list(polys.values().as_ref());
+            let coords = Coords::new(rings.values().as_ref());
+            (0..n)
+                .map(|i| {
+                    Some(Geometry::Polygon(polygon(
+                        rings,
+                        &coords,
+                        offsets(polys, i),
+                    )))
+                })
+                .collect()
+        }
+        "geoarrow.multipoint" => {
+            let mpnts = as_list(x);
+            let coords = Coords::new(mpnts.values().as_ref());
+            (0..n)
+                .map(|i| {
+                    let pnts = offsets(mpnts, i).map(|j| Point(coords.coord(j)));
+                    Some(Geometry::MultiPoint(MultiPoint::new(pnts.collect())))
+                })
+                .collect()
+        }
+        "geoarrow.multilinestring" => {
+            let mlines = as_list(x);
+            let lines = as_list(mlines.values().as_ref());
+            let coords = Coords::new(lines.values().as_ref());
+            (0..n)
+                .map(|i| {
+                    let lns = offsets(mlines, i).map(|j| coords.linestring(offsets(lines, j)));
+                    Some(Geometry::MultiLineString(MultiLineString::new(
+                        lns.collect(),
+                    )))
+                })
+                .collect()
+        }
+        "geoarrow.multipolygon" => {
+            let mpolys = as_list(x);
+            let polys = as_list(mpolys.values().as_ref());
+            let rings = as_list(polys.values().as_ref());
+            let coords = Coords::new(rings.values().as_ref());
+            (0..n)
+                .map(|i| {
+                    let plys =
+                        offsets(mpolys, i).map(|j| polygon(rings, &coords, offsets(polys, j)));
+                    Some(Geometry::MultiPolygon(MultiPolygon::new(plys.collect())))
+                })
+                .collect()
+        }
+        "geoarrow.wkb" => {
+            let values = if let Some(bin) = x.as_binary_opt::<i32>() {
+                bin.iter().collect::<Vec<_>>()
+            } else if let Some(bin) = x.as_binary_opt::<i64>() {
+                bin.iter().collect::<Vec<_>>()
+            } else {
+                panic!(
+                    "`geoarrow.wkb` must be a binary array, found `{}`",
+                    x.data_type()
+                )
+            };
+
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, xi)| match xi {
+                    Some(bytes) => {
+                        wkb_to_geometry(bytes)
+                            .unwrap_or_else(|e| {
+                                panic!("failed to parse WKB at element {}: {e}", i + 1)
+                            })
+                            .0
+                    }
+                    None => None,
+                })
+                .collect()
+        }
+        "geoarrow.wkt" => {
+            let values = if let Some(s) = x.as_string_opt::<i32>() {
+                s.iter().collect::<Vec<_>>()
+            } else if let Some(s) = x.as_string_opt::<i64>() {
+                s.iter().collect::<Vec<_>>()
+            } else {
+                panic!(
+                    "`geoarrow.wkt` must be a string array, found `{}`",
+                    x.data_type()
+                )
+            };
+
+            values
+                .into_iter()
+                .enumerate()
+                .map(|(i, xi)| {
+                    xi.and_then(|s| match Geometry::try_from_wkt_str(s) {
+                        Ok(g) => Some(g),
+                        Err(wkt::geo_types_from_wkt::Error::PointConversionError) => None,
+                        Err(e) => panic!("failed to parse WKT at element {}: {e}", i + 1),
+                    })
+                })
+                .collect()
+        }
+        ext => panic!("unsupported GeoArrow extension type `{ext}`"),
+    };
+
+    // null slots take precedence over whatever is stored in them
+    res.into_iter()
+        .enumerate()
+        .map(|(i, g)| if x.is_valid(i) { g } else { None })
+        .collect()
+}
+
+// Export -----------------------------------------------------------------
+
+#[extendr]
+fn geoarrow_from_geoms_(x: List, wkb: bool, interleaved: bool, array: Robj, schema: Robj) {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let array_ptr = unsafe { R_ExternalPtrAddr(array.get()) as *mut FFI_ArrowArray };
+    let schema_ptr = unsafe { R_ExternalPtrAddr(schema.get()) as *mut FFI_ArrowSchema };
+
+    if array_ptr.is_null() || schema_ptr.is_null() {
+        panic!("invalid Arrow array or schema")
+    }
+
+    let geom_type = rsgeo_type(&x);
+    let geoms = geometry_from_list(x);
+
+    let (extension, arr) = if wkb {
+        ("geoarrow.wkb", wkb_array(&geoms))
+    } else {
+        let coords = CoordBuilder::new(interleaved);
+        match geom_type.as_str() {
+            "point" => ("geoarrow.point", point_array(&geoms, coords)),
+            "linestring" => ("geoarrow.linestring", linestring_array(&geoms, coords)),
+            "polygon" => ("geoarrow.polygon", polygon_array(&geoms, coords)),
+            "multipoint" => ("geoarrow.multipoint", multipoint_array(&geoms, coords)),
+            "multilinestring" => (
+                "geoarrow.multilinestring",
+                multilinestring_array(&geoms, coords),
+            ),
+            "multipolygon" => ("geoarrow.multipolygon", multipolygon_array(&geoms, coords)),
+            _ => panic!(
+                "`{geom_type}` geometries have no native GeoArrow encoding, use `encoding = \"wkb\"`"
+            ),
+        }
+    };
+
+    let metadata = HashMap::from([
+        ("ARROW:extension:name".to_string(), extension.to_string()),
+        ("ARROW:extension:metadata".to_string(), "{}".to_string()),
+    ]);
+
+    let field = Field::new("", arr.data_type().clone(), true).with_metadata(metadata);
+
+    let ffi_schema = FFI_ArrowSchema::try_from(&field)
+        .unwrap_or_else(|e| panic!("failed to export Arrow schema: {e}"));
+    let ffi_array = FFI_ArrowArray::new(&arr.to_data());
+
+    // the destinations are freshly allocated and have not been initialized
+    unsafe {
+        std::ptr::write(array_ptr, ffi_array);
+        std::ptr::write(schema_ptr, ffi_schema);
+    }
+}
+
+struct CoordBuilder {
+    interleaved: bool,
+    x: Vec<f64>,
+    y: Vec<f64>,
+}
+
+impl CoordBuilder {
+    fn new(interleaved: bool) -> Self {
+        CoordBuilder {
+            interleaved,
+            x: Vec::new(),
+            y: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> i32 {
+        offset(self.x.len())
+    }
+
+    fn push(&mut self, crd: Coord) {
+        self.x.push(crd.x);
+        self.y.push(crd.y);
+    }
+
+    fn extend(&mut self, x: &LineString) {
+        x.0.iter().for_each(|crd| self.push(*crd));
+    }
+
+    fn finish(self, nulls: Option<NullBuffer>) -> ArrayRef {
+        if self.interleaved {
+            let xy = self
+                .x
+                .into_iter()
+                .zip(self.y)
+                .flat_map(|(x, y)| [x, y])
+                .collect::<Vec<f64>>();
+
+            Arc::new(FixedSizeListArray::new(
+                Arc::new(Field::new("xy", DataType::Float64, false)),
+                2,
+                Arc::new(Float64Array::from(xy)),
+                nulls,
+            ))
+        } else {
+            let fields = Fields::from(vec![
+                Field::new("x", DataType::Float64, false),
+                Field::new("y", DataType::Float64, false),
+            ]);
+
+            Arc::new(StructArray::new(
+                fields,
+                vec![
+                    Arc::new(Float64Array::from(self.x)),
+                    Arc::new(Float64Array::from(self.y)),
+                ],
+                nulls,
+            ))
+        }
+    }
+}
+
+fn validity(x: &[Option<Geometry>]) -> Option<NullBuffer> {
+    if x.iter().any(|xi| xi.is_none()) {
+        Some(NullBuffer::from(
+            x.iter().map(|xi| xi.is_some()).collect::<Vec<bool>>(),
+        ))
+    } else {
+        None
+    }
+}
+
+// GeoArrow arrays use 32-bit offsets
+fn offset(n: usize) -> i32 {
+    i32::try_from(n)
+        .unwrap_or_else(|_| panic!("`x` is too large for a GeoArrow array with 32-bit offsets"))
+}
+
+fn list_array(
+    name: &str,
+    offsets: Vec<i32>,
+    values: ArrayRef,
+    nulls: Option<NullBuffer>,
+) -> ArrayRef {
+    let field = Field::new(name, values.data_type().clone(), false);
+    Arc::new(ListArray::new(
+        Arc::new(field),
+        OffsetBuffer::new(ScalarBuffer::from(offsets)),
+        values,
+        nulls,
+    ))
+}
+
+// an empty exterior ring is an empty polygon with no rings
+fn push_polygon(x: &Polygon, coords: &mut CoordBuilder, ring_offsets: &mut Vec<i32>) {
+    if x.exterior().0.is_empty() {
+        return;
+    }
+
+    for ring in std::iter::once(x.exterior()).chain(x.interiors()) {
+        coords.extend(ring);
+        ring_offsets.push(coords.len());
+    }
+}
+
+fn point_array(x: &[Option<Geometry>], mut coords: CoordBuilder) -> ArrayRef {
+    for xi in x {
+        match xi {
+            Some(Geometry::Point(p)) => coords.push(p.0),
+            _ => coords.push(Coord {
+                x: f64::NAN,
+                y: f64::NAN,
+            }),
+        }
+    }
+
+    coords.finish(validity(x))
+}
+
+fn linestring_array(x: &[Option<Geometry>], mut coords: CoordBuilder) -> ArrayRef {
+    let mut geom_offsets = vec![0];
+
+    for xi in x {
+        if let Some(Geometry::LineString(l)) = xi {
+            coords.extend(l);
+        }
+        geom_offsets.push(coords.len());
+    }
+
+    list_array("vertices", geom_offsets, coords.finish(None), validity(x))
+}
+
+fn polygon_array(x: &[Option<Geometry>], mut coords: CoordBuilder) -> ArrayRef {
+    let mut geom_offsets = vec![0];
+    let mut ring_offsets = vec![0];
+
+    for xi in x {
+        if let Some(Geometry::Polygon(p)) = xi {
+            push_polygon(p, &mut coords, &mut ring_offsets);
+        }
+        geom_offsets.push(offset(ring_offsets.len() - 1));
+    }
+
+    let rings = list_array("vertices", ring_offsets, coords.finish(None), None);
+    list_array("rings", geom_offsets, rings, validity(x))
+}
+
+fn multipoint_array(x: &[Option<Geometry>], mut coords: CoordBuilder) -> ArrayRef {
+    let mut geom_offsets = vec![0];
+
+    for xi in x {
+        if let Some(Geometry::MultiPoint(mp)) = xi {
+            mp.0.iter().for_each(|p| coords.push(p.0));
+        }
+        geom_offsets.push(coords.len());
+    }
+
+    list_array("points", geom_offsets, coords.finish(None), validity(x))
+}
+
+fn multilinestring_array(x: &[Option<Geometry>], mut coords: CoordBuilder) -> ArrayRef {
+    let mut geom_offsets = vec![0];
+    let mut line_offsets = vec![0];
+
+    for xi in x {
+        if let Some(Geometry::MultiLineString(ml)) = xi {
+            for l in ml.0.iter() {
+                coords.extend(l);
+                line_offsets.push(coords.len());
+            }
+        }
+        geom_offsets.push(offset(line_offsets.len() - 1));
+    }
+
+    let lines = list_array("vertices", line_offsets, coords.finish(None), None);
+    list_array("linestrings", geom_offsets, lines, validity(x))
+}
+
+fn multipolygon_array(x: &[Option<Geometry>], mut coords: CoordBuilder) -> ArrayRef {
+    let mut geom_offsets = vec![0];
+    let mut poly_offsets = vec![0];
+    let mut ring_offsets = vec![0];
+
+    for xi in x {
+        if let Some(Geometry::MultiPolygon(mp)) = xi {
+            for p in mp.0.iter() {
+                push_polygon(p, &mut coords, &mut ring_offsets);
+                poly_offsets.push(offset(ring_offsets.len() - 1));
+            }
+        }
+        geom_offsets.push(offset(poly_offsets.len() - 1));
+    }
+
+    let rings = list_array("vertices", ring_offsets, coords.finish(None), None);
+    let polys = list_array("rings", poly_offsets, rings, None);
+    list_array("polygons", geom_offsets, polys, validity(x))
+}
+
+fn wkb_array(x: &[Option<Geometry>]) -> ArrayRef {
+    let res = x
+        .iter()
+        .map(|xi| xi.as_ref().map(|g| geometry_to_wkb(g, true, None)))
+        .collect::<BinaryArray>();
+
+    Arc::new(res)
+}
+
+extendr_module! {
+    mod geoarrowimpl;
+    fn geoarrow_to_geoms_;
+    fn geoarrow_from_geoms_;
+}
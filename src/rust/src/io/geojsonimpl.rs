@@ -1,522 +1,461 @@
-use crate::types::Geom;
 use extendr_api::prelude::*;
-use extendr_api::serializer::to_robj;
-use extendr_api::List;
-use geo_types::Geometry;
-
-use crate::to_pntr;
+use geo::orient::{Direction, Orient};
+use geo::{BoundingRect, MapCoords};
+use geo_types::{coord, Geometry, GeometryCollection};
+use geojson::{Feature, FeatureCollection, GeoJson, JsonObject, JsonValue};
 use rayon::prelude::*;
-use serde_json::Map;
-use serde_json::Value;
-
-use geojson::GeoJson;
-
-//#[extendr]
-// pub fn read_geojson(file: &str) -> List {
-//     let file = std::fs::File::open(file)
-//         .expect("this to work");
-
-//     let data = geojson::FeatureReader::from_reader(file);
-//     let mut obs = data.features();
+use sfconversions::geometry_from_list;
 
-//     let x = data.deserialize();
-//     let x = x.unwrap();
-//     x.into_iter()
-//         .map(|w | w.unwrap())
+use std::collections::{HashMap, VecDeque};
+use std::io::{BufRead, BufReader};
 
-// }
+use crate::utils::geoms_to_rsgeo;
 
 #[extendr]
-pub fn read_gj_props(file: &str) -> Robj {
-    let file = std::fs::File::open(file).expect("geojson file to be found");
-    let reader = BufReader::new(file);
+fn read_geojson_(file: &str) -> List {
+    let f = std::fs::File::open(file).unwrap_or_else(|e| panic!("unable to open `{file}`: {e}"));
 
-    rprintln!("BufReader created");
-
-    let data = geojson::FeatureReader::from_reader(reader);
-    let res = data
+    // features are streamed from the file rather than read into a string
+    let features = geojson::FeatureReader::from_reader(BufReader::new(f))
         .features()
-        .par_bridge()
-        .into_par_iter()
-        .map(|feat| feat.unwrap().properties.unwrap())
-        .collect::<Vec<Map<String, Value>>>();
-
-    rprintln!("results collected into vec of hashmaps");
+        .enumerate()
+        .map(|(i, feat)| feat.unwrap_or_else(|e| panic!("failed to parse feature {}: {e}", i + 1)))
+        .collect::<Vec<Feature>>();
 
-    let n = res.len();
-    let (res_vec, keys) = process_properties(res);
-
-    let index = (1..n + 1).map(|i| i as i32).collect::<Vec<i32>>();
-    let res = List::from_names_and_values(keys, res_vec).unwrap();
-
-    res.set_attrib("class", "data.frame")
-        .unwrap()
-        .set_attrib("row.names", index)
-        .unwrap()
+    features_to_list(features)
 }
 
 #[extendr]
-pub fn read_geojson_features(file: &str) -> List {
-    //let file = std::fs::File::open(file).expect("geojson file to be found");
-
-    // this reads the entire thing into a string this cannot be efficient
-    let geojson_str = std::fs::read_to_string(file).unwrap();
-    let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-    // read only the geometry
-    // it doesn't assign the proper class. i suspect this can be fixed on the
-    // r side quickly
-    // adapted / stolen directly from https://github.com/urschrei/geojson_example/blob/master/src/owned.rs
-    let res = match geojson {
-        GeoJson::FeatureCollection(collection) => collection
-            .features
-            .into_par_iter()
-            .map(|geo| Geometry::try_from(geo.geometry.unwrap()).unwrap())
-            .collect::<Vec<Geometry>>(),
-        GeoJson::Feature(feature) => {
-            vec![Geometry::try_from(feature.geometry.unwrap()).unwrap()]
-        }
-
-        GeoJson::Geometry(geom) => {
-            vec![Geometry::try_from(geom).unwrap()]
-        }
+fn read_geojson_str_(x: &str) -> List {
+    let geojson = x
+        .parse::<GeoJson>()
+        .unwrap_or_else(|e| panic!("failed to parse GeoJSON: {e}"));
+
+    let features = match geojson {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(geom) => vec![Feature::from(geom)],
     };
 
-    res.into_iter()
-        .map(|geom| to_pntr(Geom::from(geom)))
-        .collect::<List>()
+    features_to_list(features)
 }
 
 #[extendr]
-pub fn read_geojson_string(geojson_str: String) -> List {
-    //let file = std::fs::File::open(file).expect("geojson file to be found");
-    //let geojson_str = std::fs::read_to_string(file).unwrap();
-    let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-    // read only the geometry
-    // it doesn't assign the proper class. i suspect this can be fixed on the
-    // r side quickly
-    // adapted / stolen directly from https://github.com/urschrei/geojson_example/blob/master/src/owned.rs
-    let res = match geojson {
-        GeoJson::FeatureCollection(collection) => collection
-            .features
-            .into_par_iter()
-            .map(|geo| Geometry::try_from(geo.geometry.unwrap()).unwrap())
-            .collect::<Vec<Geometry>>(),
-        GeoJson::Feature(feature) => {
-            vec![Geometry::try_from(feature.geometry.unwrap()).unwrap()]
-        }
+fn read_geojsonl_(file: &str, chunk_size: i32, callback: Function) -> i32 {
+    if chunk_size < 1 {
+        panic!("`chunk_size` must be a positive integer")
+    }
 
-        GeoJson::Geometry(geom) => {
-            vec![Geometry::try_from(geom).unwrap()]
-        }
-    };
+    let f = std::fs::File::open(file).unwrap_or_else(|e| panic!("unable to open `{file}`: {e}"));
+    let mut lines = BufReader::new(f)
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let line = line.unwrap_or_else(|e| panic!("unable to read `{file}`: {e}"));
+            (i, line)
+        })
+        .filter(|(_, line)| !is_blank_line(line));
+
+    let chunk_size = chunk_size as usize;
+    let mut pending: VecDeque<Feature> = VecDeque::new();
+    let mut eof = false;
+    let mut n_features = 0;
+
+    loop {
+        // Lines are read until a full chunk of features is available. Every
+        // line has at least one feature so no more lines are read than are
+        // needed. A FeatureCollection may have more features than a chunk in
+        // which case they are split across chunks.
+        while !eof && pending.len() < chunk_size {
+            let batch = lines
+                .by_ref()
+                .take(chunk_size - pending.len())
+                .collect::<Vec<(usize, String)>>();
+
+            if batch.is_empty() {
+                eof = true;
+            }
 
-    res.into_iter()
-        .map(|geom| to_pntr(Geom::from(geom)))
-        .collect::<List>()
-}
+            let features = batch
+                .into_par_iter()
+                .flat_map_iter(|(i, line)| {
+                    parse_geojson_line(&line)
+                        .unwrap_or_else(|e| panic!("failed to parse line {}: {e}", i + 1))
+                })
+                .collect::<Vec<Feature>>();
 
-#[extendr]
-pub fn read_geojson_props(file: &str) -> Robj {
-    let geojson_str = std::fs::read_to_string(file).unwrap();
-    let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-    let res = match geojson {
-        GeoJson::FeatureCollection(collection) => collection
-            .features
-            .into_par_iter()
-            .map(|feat| feat.properties.unwrap())
-            .collect::<Vec<Map<String, Value>>>(),
-        GeoJson::Feature(feature) => {
-            vec![feature.properties.unwrap()]
+            pending.extend(features);
         }
 
-        GeoJson::Geometry(_geom) => {
-            vec![]
+        if pending.is_empty() {
+            break;
         }
-    };
 
-    let n = res.len();
-    let (res_vec, keys) = process_properties(res);
+        let n = pending.len().min(chunk_size);
+        let features = pending.drain(..n).collect::<Vec<Feature>>();
+        n_features += features.len();
 
-    let index = (1..n + 1).map(|i| i as i32).collect::<Vec<i32>>();
-    let res = List::from_names_and_values(keys, res_vec).unwrap();
+        let res = callback
+            .call(pairlist!(features_to_list(features)))
+            .unwrap_or_else(|e| panic!("`callback` failed: {e}"));
 
-    res.set_attrib("class", "data.frame")
-        .unwrap()
-        .set_attrib("row.names", index)
-        .unwrap()
-}
-
-#[extendr]
-pub fn read_props(file: &str) -> Robj {
-    let file = std::fs::File::open(file).expect("this to work");
+        // returning FALSE from the callback stops reading
+        if res.as_bool() == Some(false) {
+            break;
+        }
+    }
 
-    let reader = BufReader::new(file);
+    n_features as i32
+}
 
-    let data = geojson::FeatureReader::from_reader(reader);
+// Blank lines, which may only contain the record separator, are skipped
+fn is_blank_line(x: &str) -> bool {
+    x.trim_start_matches('\u{1e}').trim().is_empty()
+}
 
-    let res = data
-        .features()
-        // .par_bridge()
-        //  .into_par_iter()
-        .map(|feat| feat.unwrap().properties.unwrap())
-        .collect::<Vec<Map<String, Value>>>();
+// Parses a single line of GeoJSONSeq. Lines may be prefixed with the
+// record separator.
+fn parse_geojson_line(x: &str) -> std::result::Result<Vec<Feature>, geojson::Error> {
+    let x = x.trim_start_matches('\u{1e}').trim();
 
-    // rprintln!("no problem here no");
-    let n = res.len();
-    let (res_vec, keys) = process_properties(res);
-
-    let index = (1..n + 1).map(|i| i as i32).collect::<Vec<i32>>();
-    let res = List::from_names_and_values(keys, res_vec).unwrap();
+    let res = match x.parse::<GeoJson>()? {
+        GeoJson::FeatureCollection(collection) => collection.features,
+        GeoJson::Feature(feature) => vec![feature],
+        GeoJson::Geometry(geom) => vec![Feature::from(geom)],
+    };
 
-    res.set_attrib("class", "data.frame")
-        .unwrap()
-        .set_attrib("row.names", index)
-        .unwrap()
+    Ok(res)
 }
 
-#[extendr]
-pub fn read_props_string(geojson_str: String) -> Robj {
-    let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-    let res = match geojson {
-        GeoJson::FeatureCollection(collection) => collection
-            .features
-            .into_par_iter()
-            .map(|feat| feat.properties.unwrap())
-            .collect::<Vec<Map<String, Value>>>(),
-        GeoJson::Feature(feature) => {
-            vec![feature.properties.unwrap()]
-        }
-
-        GeoJson::Geometry(_geom) => {
-            vec![]
-        }
-    };
+// Splits features into an rsgeo vector and a data.frame of their properties
+pub fn features_to_list(features: Vec<Feature>) -> List {
+    let (geoms, props): (Vec<Option<Geometry>>, Vec<Option<JsonObject>>) = features
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, feat)| {
+            let geom = feat.geometry.map(|g| {
+                Geometry::try_from(&g.value)
+                    .unwrap_or_else(|e| panic!("invalid geometry in feature {}: {e}", i + 1))
+            });
+            (geom, feat.properties)
+        })
+        .unzip();
 
-    let n = res.len();
-    let (res_vec, keys) = process_properties(res);
+    list!(
+        geometry = geoms_to_rsgeo(geoms),
+        properties = process_properties(props)
+    )
+}
 
-    let index = (1..n + 1).map(|i| i as i32).collect::<Vec<i32>>();
-    let res = List::from_names_and_values(keys, res_vec).unwrap();
+// Column types ordered such that a column is promoted to the
+// largest type found in any of its values
+#[derive(Clone, Copy, PartialEq, PartialOrd)]
+pub enum ColType {
+    Null,
+    Logical,
+    Integer,
+    Double,
+    Character,
+}
 
-    res.set_attrib("class", "data.frame")
-        .unwrap()
-        .set_attrib("row.names", index)
-        .unwrap()
+fn match_type(x: &JsonValue) -> ColType {
+    match x {
+        JsonValue::Null => ColType::Null,
+        JsonValue::Bool(_) => ColType::Logical,
+        // i32::MIN is R's integer NA so it must be a double
+        JsonValue::Number(n) => match n.as_i64() {
+            Some(i) if i > i32::MIN as i64 && i <= i32::MAX as i64 => ColType::Integer,
+            _ => ColType::Double,
+        },
+        _ => ColType::Character,
+    }
 }
 
-fn match_type(x: &Map<String, Value>) -> Vec<&str> {
-    x.values()
-        .map(|val| match val {
-            Value::Number(_) => "double",
-            Value::String(_) => "character",
-            Value::Bool(_) => "logical",
-            _ => "idfk",
-        })
-        .collect::<Vec<&str>>()
+// Creates a data.frame from feature properties. Keys are ordered by their
+// first appearance and features missing a key are given an NA.
+pub fn process_properties(res: Vec<Option<JsonObject>>) -> Robj {
+    process_properties_with_columns(res, Vec::new())
 }
 
-use std::io::BufReader;
-//use std::io::BufReader;
-use std::io::prelude::*;
-#[extendr]
-fn read_geojsonl(file: &str) -> Robj {
-    let file = std::fs::File::open(file).unwrap();
-
-    let f = std::io::BufReader::new(file).lines();
-
-    rprintln!("we haven't processed yet");
-
-    //parallely collect data into a vec of tuples going through the file one line at a time
-    let res = f
-        .par_bridge()
-        .flat_map(|line| {
-            let geojson_str = line.unwrap();
-            let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-            match geojson {
-                GeoJson::FeatureCollection(collection) => collection
-                    .features
-                    .into_iter()
-                    .map(|feat| {
-                        // extract the properties and the geometry at once
-                        let props = feat.properties.unwrap();
-                        let geom = Geometry::try_from(feat.geometry.unwrap()).unwrap();
-                        // store it into a tuple
-                        (props, geom)
-                    })
-                    .collect::<Vec<(Map<String, Value>, Geometry)>>(),
+// Creates a data.frame from feature properties starting from known columns,
+// such as those declared in a file header, so that they keep their order and
+// type even when values are missing. Their types are still promoted by the
+// values found and any other keys follow them.
+pub fn process_properties_with_columns(
+    res: Vec<Option<JsonObject>>,
+    columns: Vec<(String, ColType)>,
+) -> Robj {
+    let n = res.len();
 
-                GeoJson::Feature(feature) => {
-                    vec![(
-                        feature.properties.unwrap(),
-                        Geometry::try_from(feature.geometry.unwrap()).unwrap(),
-                    )]
-                }
+    let mut keys: Vec<String> = Vec::new();
+    let mut key_index: HashMap<String, usize> = HashMap::new();
+    let mut col_types: Vec<ColType> = Vec::new();
 
-                GeoJson::Geometry(geom) => {
-                    vec![(serde_json::Map::new(), Geometry::try_from(geom).unwrap())]
+    for (key, ctype) in columns {
+        key_index.insert(key.clone(), keys.len());
+        keys.push(key);
+        col_types.push(ctype);
+    }
+
+    for (k, v) in res.iter().flatten().flat_map(|obs| obs.iter()) {
+        let vtype = match_type(v);
+        match key_index.get(k) {
+            Some(&i) => {
+                if vtype > col_types[i] {
+                    col_types[i] = vtype;
                 }
             }
+            None => {
+                key_index.insert(k.to_owned(), keys.len());
+                keys.push(k.to_owned());
+                col_types.push(vtype);
+            }
+        }
+    }
+
+    let res_vec = keys
+        .iter()
+        .zip(col_types.iter())
+        .map(|(key, ctype)| {
+            let vals = res
+                .iter()
+                .map(|obs| obs.as_ref().and_then(|o| o.get(key)))
+                .collect::<Vec<Option<&JsonValue>>>();
+            json_column(vals, *ctype)
         })
-        .collect::<Vec<(Map<String, Value>, Geometry)>>();
-
-    //let mut res_props: Arc<Mutex<Vec<Map<String, Value>>>> =  Arc::new(Mutex::new(Vec::new()));
-    //let mut res_geos: Vec<Geometry> = Vec::new();
-
-    // let res_props = f
-    //     .by_ref()
-    //     .par_bridge()
-    //     .flat_map(|line| {
-    //         let geojson_str = line.unwrap();
-    //         let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-    //         match geojson {
-    //             GeoJson::FeatureCollection(collection) => collection
-    //                 .features
-    //                 .into_par_iter()
-    //                 .map(|feat| feat.properties.unwrap())
-    //                 .collect::<Vec<Map<String, Value>>>(),
-    //             GeoJson::Feature(feature) => {
-    //                 vec![feature.properties.unwrap()]
-    //             }
-
-    //             GeoJson::Geometry(_geom) => {
-    //                 vec![]
-    //             }
-    //         }
-    //     })
-    //     .collect::<Vec<Map<String, Value>>>();
-
-    // let res_geos = f
-    //     .par_bridge()
-    //     .flat_map(|line| {
-    //         let geojson_str = line.unwrap();
-    //         let geojson = geojson_str.parse::<GeoJson>().unwrap();
-
-    //         match geojson {
-    //             GeoJson::FeatureCollection(collection) => collection
-    //                 .features
-    //                 .into_par_iter()
-    //                 .map(|geo| Geometry::try_from(geo.geometry.unwrap()).unwrap())
-    //                 .collect::<Vec<Geometry>>(),
-    //             GeoJson::Feature(feature) => {
-    //                 vec![Geometry::try_from(feature.geometry.unwrap()).unwrap()]
-    //             }
-
-    //             GeoJson::Geometry(geom) => {
-    //                 vec![Geometry::try_from(geom).unwrap()]
-    //             }
-    //         }
-    //     })
-    //     .collect::<Vec<Geometry>>();
-    let prop_maps = res
-        .par_iter()
-        .map(|x| x.0.to_owned())
-        .collect::<Vec<Map<String, Value>>>();
-
-    rprintln!("properties extracted");
-
-    let (mut res_vec, mut keys) = process_properties(prop_maps);
-
-    rprintln!("properties processed into vec<robj>");
-
-    // // convert to list of pointers
-    let geoms = res
-        .into_iter()
-        .map(|x| x.1.to_owned())
-        .collect::<Vec<Geometry<f64>>>()
-        .into_iter()
-        .map(|x| to_pntr(Geom::from(x)))
-        .collect::<List>()
-        .set_attrib("class", crate::utils::geom_class("geometrycollection"))
-        .unwrap();
-
-    res_vec.push(geoms);
-
-    keys.push(String::from("geometry"));
-
-    let index = (1..res_vec[0].len() + 1)
-        .map(|i| i as i32)
-        .collect::<Vec<i32>>();
-    let res = List::from_names_and_values(keys, res_vec).unwrap();
-
-    res.set_attrib("class", "data.frame")
+        .collect::<Vec<Robj>>();
+
+    let index = (1..n + 1).map(|i| i as i32).collect::<Vec<i32>>();
+
+    List::from_names_and_values(keys, res_vec)
+        .unwrap()
+        .set_attrib("class", "data.frame")
         .unwrap()
         .set_attrib("row.names", index)
         .unwrap()
+        .clone()
+        .into_robj()
 }
 
-pub fn process_properties(res: Vec<Map<String, Value>>) -> (Vec<Robj>, Vec<String>) {
-    // pull first element to find the keys
-    let x1 = res[0].clone();
-    // rprintln!("pulled first element");
-    // collect keys into vec of string
-    let keys = x1.keys().collect::<Vec<&String>>();
-    //rprintln!("have first element keys");
-    //let n = res.len(); // number row
-    let nn = keys.len(); // number cols
-
-    //let mut col_types: Vec<&str> = Vec::with_capacity(nn);
-    let mut col_types = match_type(&res[0]);
-    //rprintln!("matches col types for first element");
-
-    for obs in res.iter().skip(1) {
-        //        rprintln!("inner loop accessed");
-
-        let keys_i = match_type(obs);
-
-        //rprintln!("keys matched");
-
-        let which_unknown = col_types
-            .iter()
-            .enumerate() //now you have indexes :)
-            .filter(|(_index, col)| **col == "idfk")
-            .map(|(index, _)| index)
-            .collect::<Vec<usize>>();
-
-        rprintln!("identified which keys are unknown");
-        // if all aren't true break
-        if which_unknown.is_empty() {
-            // rprintln!("loop broken");
-            break;
+fn json_column(vals: Vec<Option<&JsonValue>>, ctype: ColType) -> Robj {
+    match ctype {
+        // columns that are entirely null become logical NA as in R
+        ColType::Null | ColType::Logical => vals
+            .into_iter()
+            .map(|v| match v {
+                Some(JsonValue::Bool(b)) => Rbool::from(*b),
+                _ => Rbool::na(),
+            })
+            .collect::<Logicals>()
+            .into_robj(),
+        ColType::Integer => vals
+            .into_iter()
+            .map(|v| match v {
+                Some(JsonValue::Bool(b)) => Rint::from(*b as i32),
+                Some(JsonValue::Number(n)) => Rint::from(n.as_i64().unwrap() as i32),
+                _ => Rint::na(),
+            })
+            .collect::<Integers>()
+            .into_robj(),
+        ColType::Double => vals
+            .into_iter()
+            .map(|v| match v {
+                Some(JsonValue::Bool(b)) => Rfloat::from(*b as i32 as f64),
+                Some(JsonValue::Number(n)) => n.as_f64().map(Rfloat::from).unwrap_or(Rfloat::na()),
+                _ => Rfloat::na(),
+            })
+            .collect::<Doubles>()
+            .into_robj(),
+        ColType::Character => {
+            let res_vec = vals
+                .into_iter()
+                .map(|v| match v {
+                    None | Some(JsonValue::Null) => Rstr::na(),
+                    Some(JsonValue::String(s)) => Rstr::from(s.as_str()),
+                    // use R's coercion of logicals to character
+                    Some(JsonValue::Bool(b)) => Rstr::from(if *b { "TRUE" } else { "FALSE" }),
+                    // numbers, arrays, and objects are kept as JSON
+                    Some(other) => Rstr::from(other.to_string()),
+                })
+                .collect::<Vec<Rstr>>();
+
+            Strings::from_values(res_vec).into_robj()
         }
+    }
+}
 
-        // rprintln!("filling unknown types");
-        // if not fill the types
-        for i in which_unknown.into_iter() {
-            col_types[i] = keys_i[i];
-        }
+#[extendr]
+fn write_geojson_(
+    x: List,
+    properties: List,
+    digits: Integers,
+    rfc7946: bool,
+    bbox: bool,
+) -> String {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
     }
 
-    let mut res_vec: Vec<Robj> = Vec::with_capacity(nn);
+    let n = x.len();
 
-    //rprintln!("next loop reached. creating R type columns");
+    let digits = match digits.iter().next() {
+        Some(d) if !d.is_na() => Some(d.inner()),
+        _ => None,
+    };
 
-    for (i, key) in keys.clone().into_iter().enumerate() {
-        let ctype = col_types[i];
-        //rprintln!("column {} being processed has type {}", i, ctype);
-        let col = match ctype {
-            "double" => Doubles::from_iter(
-                res.iter()
-                    .map(|x| Rfloat::try_from(x[key].as_f64()).unwrap_or(Rfloat::na())),
-            )
-            .into_robj(),
-            "character" => Strings::from_iter(res.iter().map(|x| {
-                // dies here
-                Rstr::try_from(x[key].as_str().unwrap_or(&Rstr::na())).unwrap_or(Rstr::na())
-            }))
-            .into_robj(),
-            "logical" => Logicals::from_iter(
-                res.iter()
-                    .map(|x| Rbool::try_from(x[key].as_bool().unwrap()).unwrap_or(Rbool::na())),
-            )
-            .into_robj(),
-            _ => List::from_iter(res.iter().map(|x| to_robj(&x[key]).unwrap())).into_robj(),
-        };
+    let cols = properties
+        .iter()
+        .map(|(name, col)| (name.to_string(), PropColumn::from_robj(name, col)))
+        .collect::<Vec<(String, PropColumn)>>();
 
-        res_vec.push(col)
+    if cols.iter().any(|(_, col)| col.len() != n) {
+        panic!("`properties` must have the same number of rows as the length of `x`")
     }
 
-    // rprintln!("R columns created and pushed to `res_vec`");
+    let features = geometry_from_list(x)
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, xi)| {
+            let geom = xi.map(|g| {
+                let g = match digits {
+                    Some(d) => round_coords(g, d),
+                    None => g,
+                };
+
+                if rfc7946 {
+                    orient_rfc7946(g)
+                } else {
+                    g
+                }
+            });
+
+            let feat_bbox = match (&geom, bbox) {
+                (Some(g), true) => g
+                    .bounding_rect()
+                    .map(|r| vec![r.min().x, r.min().y, r.max().x, r.max().y]),
+                _ => None,
+            };
+
+            let props = if cols.is_empty() {
+                None
+            } else {
+                Some(
+                    cols.iter()
+                        .map(|(k, col)| (k.to_owned(), col.value(i)))
+                        .collect::<JsonObject>(),
+                )
+            };
+
+            Feature {
+                bbox: feat_bbox,
+                geometry: geom.map(|g| geojson::Geometry::new(geojson::Value::from(&g))),
+                id: None,
+                properties: props,
+                foreign_members: None,
+            }
+        })
+        .collect::<Vec<Feature>>();
 
-    let keys = keys
-        .into_iter()
-        .map(|key| key.to_owned())
-        .collect::<Vec<String>>();
-    (res_vec, keys)
+    FeatureCollection::from_iter(features).to_string()
 }
 
-pub fn process_props(res: Vec<Map<String, Value>>) -> (Vec<Robj>, Vec<String>) {
-    // pull first element to find the keys
-    let x1 = res[0].clone();
-    // rprintln!("pulled first element");
-    // collect keys into vec of string
-    let keys = x1.keys().collect::<Vec<&String>>();
-    //rprintln!("have first element keys");
-    //let n = res.len(); // number row
-    let nn = keys.len(); // number cols
-
-    //let mut col_types: Vec<&str> = Vec::with_capacity(nn);
-    let mut col_types = match_type(&res[0]);
-    //rprintln!("matches col types for first element");
-
-    for obs in res.iter().skip(1) {
-        //rprintln!("inner loop accessed");
-
-        let keys_i = match_type(obs);
-
-        rprintln!("keys matched");
-
-        let which_unknown = col_types
-            .iter()
-            .enumerate() //now you have indexes :)
-            .filter(|(_index, col)| **col == "idfk")
-            .map(|(index, _)| index)
-            .collect::<Vec<usize>>();
-
-        rprintln!("identified which keys are unknown");
-        // if all aren't true break
-        if which_unknown.is_empty() {
-            rprintln!("loop broken");
-            break;
-        }
+// Columns of a data.frame that can be written as feature properties
+pub enum PropColumn {
+    Logical(Vec<Option<bool>>),
+    Integer(Vec<Option<i32>>),
+    Double(Vec<Option<f64>>),
+    Character(Vec<Option<String>>),
+}
 
-        rprintln!("filling unknown types");
-        // if not fill the types
-        for i in which_unknown.into_iter() {
-            col_types[i] = keys_i[i];
+impl PropColumn {
+    pub fn from_robj(name: &str, x: Robj) -> Self {
+        match x.rtype() {
+            Rtype::Logicals => PropColumn::Logical(
+                Logicals::try_from(x)
+                    .unwrap()
+                    .iter()
+                    .map(|xi| if xi.is_na() { None } else { Some(xi.is_true()) })
+                    .collect(),
+            ),
+            Rtype::Integers => PropColumn::Integer(
+                Integers::try_from(x)
+                    .unwrap()
+                    .iter()
+                    .map(|xi| if xi.is_na() { None } else { Some(xi.inner()) })
+                    .collect(),
+            ),
+            Rtype::Doubles => PropColumn::Double(
+                Doubles::try_from(x)
+                    .unwrap()
+                    .iter()
+                    .map(|xi| if xi.is_na() { None } else { Some(xi.inner()) })
+                    .collect(),
+            ),
+            Rtype::Strings => PropColumn::Character(
+                Strings::try_from(x)
+                    .unwrap()
+                    .iter()
+                    .map(|xi| {
+                        if xi.is_na() {
+                            None
+                        } else {
+                            Some(xi.as_str().to_owned())
+                        }
+                    })
+                    .collect(),
+            ),
+            _ => panic!("property `{name}` must be logical, integer, double, or character"),
         }
     }
 
-    let mut res_vec: Vec<Robj> = Vec::with_capacity(nn);
-
-    rprintln!("next loop reached. creating R type columns");
-
-    for (i, key) in keys.clone().into_iter().enumerate() {
-        let ctype = col_types[i];
-        //rprintln!("column {} being processed has type {}", i, ctype);
-        let col = match ctype {
-            "double" => Doubles::from_iter(
-                res.iter()
-                    .map(|x| Rfloat::try_from(x[key].as_f64()).unwrap_or(Rfloat::na())),
-            )
-            .into_robj(),
-            "character" => Strings::from_iter(res.iter().map(|x| {
-                // dies here
-                x[key].to_string()
-            }))
-            .into_robj(),
-            "logical" => Logicals::from_iter(
-                res.iter()
-                    .map(|x| Rbool::try_from(x[key].as_bool().unwrap()).unwrap_or(Rbool::na())),
-            )
-            .into_robj(),
-            _ => List::from_iter(res.iter().map(|x| to_robj(&x[key]).unwrap())).into_robj(),
-        };
+    pub fn len(&self) -> usize {
+        match self {
+            PropColumn::Logical(x) => x.len(),
+            PropColumn::Integer(x) => x.len(),
+            PropColumn::Double(x) => x.len(),
+            PropColumn::Character(x) => x.len(),
+        }
+    }
 
-        res_vec.push(col)
+    // missing values are written as null
+    fn value(&self, i: usize) -> JsonValue {
+        match self {
+            PropColumn::Logical(x) => x[i].map(JsonValue::from).unwrap_or(JsonValue::Null),
+            PropColumn::Integer(x) => x[i].map(JsonValue::from).unwrap_or(JsonValue::Null),
+            PropColumn::Double(x) => x[i].map(JsonValue::from).unwrap_or(JsonValue::Null),
+            PropColumn::Character(x) => x[i]
+                .as_ref()
+                .map(|s| JsonValue::from(s.as_str()))
+                .unwrap_or(JsonValue::Null),
+        }
     }
+}
 
-    rprintln!("R columns created and pushed to `res_vec`");
+// round coordinates to a fixed number of decimal places
+fn round_coords(x: Geometry, digits: i32) -> Geometry {
+    let scale = 10_f64.powi(digits);
+    x.map_coords(|c| coord! { x: (c.x * scale).round() / scale, y: (c.y * scale).round() / scale })
+}
 
-    let keys = keys
-        .into_iter()
-        .map(|key| key.to_owned())
-        .collect::<Vec<String>>();
-    (res_vec, keys)
+// RFC 7946 requires counter-clockwise exterior rings and clockwise
+// interior rings which is the default orientation in geo
+fn orient_rfc7946(x: Geometry) -> Geometry {
+    match x {
+        Geometry::Polygon(p) => Geometry::Polygon(p.orient(Direction::Default)),
+        Geometry::MultiPolygon(p) => Geometry::MultiPolygon(p.orient(Direction::Default)),
+        Geometry::Rect(r) => Geometry::Polygon(r.to_polygon().orient(Direction::Default)),
+        Geometry::Triangle(t) => Geometry::Polygon(t.to_polygon().orient(Direction::Default)),
+        Geometry::GeometryCollection(gc) => Geometry::GeometryCollection(
+            GeometryCollection::new_from(gc.0.into_iter().map(orient_rfc7946).collect()),
+        ),
+        g => g,
+    }
 }
 
 extendr_module! {
     mod geojsonimpl;
-    fn read_geojson_features;
-    fn read_geojson_string;
-    fn read_geojson_props;
-    fn read_geojsonl;
-    fn read_props;
-    fn read_gj_props;
-    fn read_props_string;
+    fn read_geojson_;
+    fn read_geojson_str_;
+    fn read_geojsonl_;
+    fn write_geojson_;
 }
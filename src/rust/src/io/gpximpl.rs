@@ -0,0 +1,106 @@
+use extendr_api::prelude::*;
+use geo_types::Geometry;
+use geojson::{JsonObject, JsonValue};
+use gpx::Waypoint;
+use std::fs::File;
+use std::io::BufReader;
+use time::OffsetDateTime;
+
+use super::geojsonimpl::process_properties;
+use super::shpimpl::unify_multi;
+use crate::utils::geoms_to_rsgeo;
+
+// times are returned as seconds since the epoch for conversion to POSIXct
+fn waypoint_time(x: &Waypoint) -> JsonValue {
+    x.time
+        .map(|t| OffsetDateTime::from(t).unix_timestamp_nanos() as f64 / 1e9)
+        .map(JsonValue::from)
+        .unwrap_or(JsonValue::Null)
+}
+
+fn opt_string(x: &Option<String>) -> JsonValue {
+    x.as_deref().map(JsonValue::from).unwrap_or(JsonValue::Null)
+}
+
+fn opt_f64(x: Option<f64>) -> JsonValue {
+    x.map(JsonValue::from).unwrap_or(JsonValue::Null)
+}
+
+fn feature_list(geoms: Vec<Option<Geometry>>, props: Vec<Option<JsonObject>>) -> List {
+    list!(
+        geometry = geoms_to_rsgeo(geoms),
+        properties = process_properties(props)
+    )
+}
+
+#[extendr]
+fn read_gpx_(file: &str) -> List {
+    let f = File::open(file).unwrap_or_else(|e| panic!("unable to open `{file}`: {e}"));
+    let gpx =
+        gpx::read(BufReader::new(f)).unwrap_or_else(|e| panic!("unable to read GPX `{file}`: {e}"));
+
+    let (wpt_geoms, wpt_props): (Vec<Option<Geometry>>, Vec<Option<JsonObject>>) = gpx
+        .waypoints
+        .iter()
+        .map(|wp| {
+            let props = JsonObject::from_iter([
+                ("name".to_string(), opt_string(&wp.name)),
+                ("description".to_string(), opt_string(&wp.description)),
+                ("elevation".to_string(), opt_f64(wp.elevation)),
+                ("time".to_string(), waypoint_time(wp)),
+            ]);
+            (Some(Geometry::Point(wp.point())), Some(props))
+        })
+        .unzip();
+
+    let (trk_geoms, trk_props): (Vec<Option<Geometry>>, Vec<Option<JsonObject>>) = gpx
+        .tracks
+        .iter()
+        .map(|trk| {
+            let props = JsonObject::from_iter([
+                ("name".to_string(), opt_string(&trk.name)),
+                ("description".to_string(), opt_string(&trk.description)),
+            ]);
+
+            // tracks without segments have no geometry
+            let geom = if trk.segments.is_empty() {
+                None
+            } else {
+                Some(Geometry::MultiLineString(trk.multilinestring()))
+            };
+
+            (geom, Some(props))
+        })
+        .unzip();
+
+    // every track point with the track and segment it belongs to
+    let (pnt_geoms, pnt_props): (Vec<Option<Geometry>>, Vec<Option<JsonObject>>) = gpx
+        .tracks
+        .iter()
+        .enumerate()
+        .flat_map(|(i, trk)| {
+            trk.segments.iter().enumerate().flat_map(move |(j, seg)| {
+                seg.points.iter().map(move |pt| {
+                    let props = JsonObject::from_iter([
+                        ("track".to_string(), JsonValue::from(i as i32 + 1)),
+                        ("segment".to_string(), JsonValue::from(j as i32 + 1)),
+                        ("elevation".to_string(), opt_f64(pt.elevation)),
+                        ("time".to_string(), waypoint_time(pt)),
+                    ]);
+                    (Some(Geometry::Point(pt.point())), Some(props))
+                })
+            })
+        })
+        .unzip();
+
+    list!(
+        waypoints = feature_list(wpt_geoms, wpt_props),
+        tracks = feature_list(unify_multi(trk_geoms), trk_props),
+        track_points = feature_list(pnt_geoms, pnt_props)
+    )
+}
+
+extendr_module! {
+    mod gpximpl;
+    fn read_gpx_;
+}
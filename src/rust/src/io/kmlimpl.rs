@@ -0,0 +1,120 @@
+use extendr_api::prelude::*;
+use geo_types::{Geometry, LineString, Polygon};
+use sfconversions::Geom;
+use std::fmt::Write;
+
+fn escape(x: &str) -> String {
+    x.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn coordinates(out: &mut String, x: &LineString) {
+    out.push_str("<coordinates>");
+    let coords =
+        x.0.iter()
+            .map(|c| format!("{},{}", c.x, c.y))
+            .collect::<Vec<String>>();
+    out.push_str(&coords.join(" "));
+    out.push_str("</coordinates>");
+}
+
+fn polygon(out: &mut String, x: &Polygon) {
+    out.push_str("<Polygon><outerBoundaryIs><LinearRing>");
+    coordinates(out, x.exterior());
+    out.push_str("</LinearRing></outerBoundaryIs>");
+
+    for ring in x.interiors() {
+        out.push_str("<innerBoundaryIs><LinearRing>");
+        coordinates(out, ring);
+        out.push_str("</LinearRing></innerBoundaryIs>");
+    }
+
+    out.push_str("</Polygon>");
+}
+
+fn geometry(out: &mut String, x: &Geometry) {
+    match x {
+        Geometry::Point(p) => write!(
+            out,
+            "<Point><coordinates>{},{}</coordinates></Point>",
+            p.x(),
+            p.y()
+        )
+        .unwrap(),
+        Geometry::Line(l) => geometry(out, &Geometry::LineString(LineString::from(*l))),
+        Geometry::LineString(l) => {
+            out.push_str("<LineString>");
+            coordinates(out, l);
+            out.push_str("</LineString>");
+        }
+        Geometry::Polygon(p) => polygon(out, p),
+        Geometry::Rect(r) => polygon(out, &r.to_polygon()),
+        Geometry::Triangle(t) => polygon(out, &t.to_polygon()),
+        // multi part geometries and collections are written as a MultiGeometry
+        Geometry::MultiPoint(mp) => multi(out, mp.0.iter().map(|p| Geometry::Point(*p))),
+        Geometry::MultiLineString(ml) => {
+            multi(out, ml.0.iter().map(|l| Geometry::LineString(l.clone())))
+        }
+        Geometry::MultiPolygon(mp) => multi(out, mp.0.iter().map(|p| Geometry::Polygon(p.clone()))),
+        Geometry::GeometryCollection(gc) => multi(out, gc.0.iter().cloned()),
+    }
+}
+
+fn multi(out: &mut String, x: impl Iterator<Item = Geometry>) {
+    out.push_str("<MultiGeometry>");
+    x.for_each(|g| geometry(out, &g));
+    out.push_str("</MultiGeometry>");
+}
+
+#[extendr]
+fn write_kml_(x: List, name: Strings, description: Strings) -> String {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let n = x.len();
+
+    // names and descriptions are optional and empty when not provided
+    let name = name.iter().collect::<Vec<Rstr>>();
+    let description = description.iter().collect::<Vec<Rstr>>();
+
+    if (!name.is_empty() && name.len() != n) || (!description.is_empty() && description.len() != n)
+    {
+        panic!("`name` and `description` must be the same length as `x`")
+    }
+
+    let mut out = String::new();
+    out.push_str(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    out.push('\n');
+    out.push_str(r#"<kml xmlns="http://www.opengis.net/kml/2.2"><Document>"#);
+    out.push('\n');
+
+    for (i, (_, xi)) in x.iter().enumerate() {
+        out.push_str("<Placemark>");
+
+        if let Some(nm) = name.get(i).filter(|s| !s.is_na()) {
+            write!(out, "<name>{}</name>", escape(nm.as_str())).unwrap();
+        }
+
+        if let Some(desc) = description.get(i).filter(|s| !s.is_na()) {
+            write!(out, "<description>{}</description>", escape(desc.as_str())).unwrap();
+        }
+
+        // missing geometries are written as placemarks without a geometry
+        if let Ok(g) = <&Geom>::try_from(&xi) {
+            geometry(&mut out, &g.geom);
+        }
+
+        out.push_str("</Placemark>\n");
+    }
+
+    out.push_str("</Document></kml>\n");
+    out
+}
+
+extendr_module! {
+    mod kmlimpl;
+    fn write_kml_;
+}
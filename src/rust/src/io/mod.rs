@@ -1,7 +1,15 @@
 use extendr_api::prelude::*;
 
+pub mod fgbimpl;
+pub mod geoarrowimpl;
 pub mod geojsonimpl;
+pub mod gpximpl;
+pub mod kmlimpl;
+pub mod mvtimpl;
+pub mod shpimpl;
+pub mod svgimpl;
 pub mod wkbimpl;
+pub mod wkimpl;
 pub mod wktimpl;
 
 extendr_module! {
@@ -9,4 +17,12 @@ extendr_module! {
     use wktimpl;
     use wkbimpl;
     use geojsonimpl;
+    use wkimpl;
+    use geoarrowimpl;
+    use fgbimpl;
+    use shpimpl;
+    use mvtimpl;
+    use svgimpl;
+    use gpximpl;
+    use kmlimpl;
 }
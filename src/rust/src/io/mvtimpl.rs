@@ -0,0 +1,352 @@
+use extendr_api::prelude::*;
+use geo::orient::{Direction, Orient};
+use geo::{Area, MapCoords};
+use geo_types::{Coord, Geometry, LineString, MultiPolygon, Polygon};
+use rayon::prelude::*;
+use sfconversions::geometry_from_list;
+use std::collections::HashMap;
+
+use super::geojsonimpl::PropColumn;
+use crate::grids::tileimpl::{clip_to_rect, lonlat_to_tile, tile_rect};
+use crate::simplification::simplify_geometry;
+
+// geometry command ids
+const MOVE_TO: u32 = 1;
+const LINE_TO: u32 = 2;
+const CLOSE_PATH: u32 = 7;
+
+// geometry types
+const POINT: u32 = 1;
+const LINESTRING: u32 = 2;
+const POLYGON: u32 = 3;
+
+// protobuf wire types
+const VARINT: u32 = 0;
+const FIXED64: u32 = 1;
+const LEN: u32 = 2;
+
+fn zigzag(x: i64) -> u64 {
+    ((x << 1) ^ (x >> 63)) as u64
+}
+
+// A minimal protobuf writer covering the field types used by the MVT spec
+#[derive(Default)]
+struct PbfWriter(Vec<u8>);
+
+impl PbfWriter {
+    fn varint(&mut self, mut x: u64) {
+        while x >= 0x80 {
+            self.0.push((x as u8) | 0x80);
+            x >>= 7;
+        }
+        self.0.push(x as u8);
+    }
+
+    fn key(&mut self, field: u32, wire_type: u32) {
+        self.varint(((field << 3) | wire_type) as u64)
+    }
+
+    fn uint(&mut self, field: u32, x: u64) {
+        self.key(field, VARINT);
+        self.varint(x);
+    }
+
+    fn sint(&mut self, field: u32, x: i64) {
+        self.key(field, VARINT);
+        self.varint(zigzag(x));
+    }
+
+    fn double(&mut self, field: u32, x: f64) {
+        self.key(field, FIXED64);
+        self.0.extend_from_slice(&x.to_le_bytes());
+    }
+
+    fn bytes(&mut self, field: u32, x: &[u8]) {
+        self.key(field, LEN);
+        self.varint(x.len() as u64);
+        self.0.extend_from_slice(x);
+    }
+
+    fn packed(&mut self, field: u32, x: &[u32]) {
+        let mut inner = PbfWriter::default();
+        x.iter().for_each(|xi| inner.varint(*xi as u64));
+        self.bytes(field, &inner.0);
+    }
+}
+
+// Encodes geometry commands. The cursor carries over between parts
+#[derive(Default)]
+struct CommandEncoder {
+    cmds: Vec<u32>,
+    cursor: (i64, i64),
+}
+
+impl CommandEncoder {
+    fn command(&mut self, id: u32, count: usize) {
+        self.cmds.push((id & 0x7) | ((count as u32) << 3));
+    }
+
+    fn coord(&mut self, c: &Coord) {
+        let (x, y) = (c.x as i64, c.y as i64);
+        self.cmds.push(zigzag(x - self.cursor.0) as u32);
+        self.cmds.push(zigzag(y - self.cursor.1) as u32);
+        self.cursor = (x, y);
+    }
+
+    fn line(&mut self, x: &[Coord]) {
+        self.command(MOVE_TO, 1);
+        self.coord(&x[0]);
+        self.command(LINE_TO, x.len() - 1);
+        x[1..].iter().for_each(|c| self.coord(c));
+    }
+
+    // the closing coordinate is implied by ClosePath
+    fn ring(&mut self, x: &[Coord]) {
+        self.line(&x[..x.len() - 1]);
+        self.command(CLOSE_PATH, 1);
+    }
+}
+
+// rounds to the tile grid and removes repeated coordinates
+fn snap_line(x: &LineString) -> LineString {
+    let mut coords =
+        x.0.iter()
+            .map(|c| Coord {
+                x: c.x.round(),
+                y: c.y.round(),
+            })
+            .collect::<Vec<Coord>>();
+    coords.dedup();
+    LineString::new(coords)
+}
+
+fn valid_ring(x: &LineString) -> bool {
+    x.0.len() >= 4 && Polygon::new(x.clone(), vec![]).unsigned_area() > 0.0
+}
+
+// Clips, projects to tile coordinates, simplifies, and encodes a geometry
+// as MVT commands. Returns `None` if nothing is left to encode.
+fn encode_geometry(
+    x: &Geometry,
+    tile: (i32, i32, i32),
+    extent: f64,
+    buffer: f64,
+    tolerance: f64,
+) -> Option<(u32, Vec<u32>)> {
+    let (tx, ty, z) = tile;
+    let clipped = clip_to_rect(x, &tile_rect(tx, ty, z, buffer / extent))?;
+
+    let projected = clipped.map_coords(|c| {
+        let (px, py) = lonlat_to_tile(c.x, c.y, z);
+        Coord {
+            x: (px - tx as f64) * extent,
+            y: (py - ty as f64) * extent,
+        }
+    });
+
+    let geom = match projected {
+        Geometry::Point(_) | Geometry::MultiPoint(_) => projected,
+        g if tolerance > 0.0 => simplify_geometry(g, tolerance)?,
+        g => g,
+    };
+
+    let mut enc = CommandEncoder::default();
+
+    let geom_type = match geom {
+        Geometry::Point(p) => {
+            enc.command(MOVE_TO, 1);
+            enc.coord(&Coord {
+                x: p.x().round(),
+                y: p.y().round(),
+            });
+            POINT
+        }
+        Geometry::MultiPoint(mp) => {
+            enc.command(MOVE_TO, mp.0.len());
+            mp.0.iter().for_each(|p| {
+                enc.coord(&Coord {
+                    x: p.x().round(),
+                    y: p.y().round(),
+                })
+            });
+            POINT
+        }
+        Geometry::MultiLineString(ml) => {
+            ml.0.iter()
+                .map(snap_line)
+                .filter(|l| l.0.len() >= 2)
+                .for_each(|l| enc.line(&l.0));
+            LINESTRING
+        }
+        Geometry::MultiPolygon(mp) => {
+            let polys =
+                mp.0.iter()
+                    .filter_map(|p| {
+                        let exterior = snap_line(p.exterior());
+                        if !valid_ring(&exterior) {
+                            return None;
+                        }
+                        let interiors = p
+                            .interiors()
+                            .iter()
+                            .map(snap_line)
+                            .filter(valid_ring)
+                            .collect();
+                        Some(Polygon::new(exterior, interiors))
+                    })
+                    .collect::<Vec<Polygon>>();
+
+            // exterior rings must have a positive area in tile coordinates
+            // which is the default orientation of geo
+            let polys = MultiPolygon::new(polys).orient(Direction::Default);
+
+            for p in polys.0.iter() {
+                enc.ring(&p.exterior().0);
+                p.interiors().iter().for_each(|r| enc.ring(&r.0));
+            }
+            POLYGON
+        }
+        // geometry collections have no representation in a vector tile
+        _ => return None,
+    };
+
+    if enc.cmds.is_empty() {
+        None
+    } else {
+        Some((geom_type, enc.cmds))
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum TagValue {
+    String(String),
+    // stored as bits so that values can be hashed
+    Double(u64),
+    Int(i64),
+    Bool(bool),
+}
+
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn mvt_encode_(
+    x: List,
+    properties: List,
+    layer: &str,
+    tx: i32,
+    ty: i32,
+    z: i32,
+    extent: i32,
+    buffer: i32,
+    tolerance: f64,
+) -> Raw {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    if extent <= 0 {
+        panic!("`extent` must be a positive integer")
+    }
+
+    let n = x.len();
+
+    let cols = properties
+        .iter()
+        .map(|(name, col)| (name.to_string(), PropColumn::from_robj(name, col)))
+        .collect::<Vec<(String, PropColumn)>>();
+
+    if cols.iter().any(|(_, col)| col.len() != n) {
+        panic!("`properties` must have the same number of rows as the length of `x`")
+    }
+
+    let geoms = geometry_from_list(x)
+        .into_par_iter()
+        .map(|xi| {
+            xi.and_then(|g| {
+                encode_geometry(&g, (tx, ty, z), extent as f64, buffer as f64, tolerance)
+            })
+        })
+        .collect::<Vec<Option<(u32, Vec<u32>)>>>();
+
+    // keys and values are shared by all features in the layer
+    let mut keys: Vec<&str> = Vec::new();
+    let mut key_index: HashMap<&str, u32> = HashMap::new();
+    let mut values: Vec<TagValue> = Vec::new();
+    let mut value_index: HashMap<TagValue, u32> = HashMap::new();
+
+    let mut lyr = PbfWriter::default();
+    lyr.uint(15, 2);
+    lyr.bytes(1, layer.as_bytes());
+
+    for (i, geom) in geoms.into_iter().enumerate() {
+        let Some((geom_type, cmds)) = geom else {
+            continue;
+        };
+
+        let mut tags = Vec::new();
+
+        // missing values are omitted from the feature
+        for (name, col) in cols.iter() {
+            let value = match col {
+                PropColumn::Logical(v) => v[i].map(TagValue::Bool),
+                PropColumn::Integer(v) => v[i].map(|vi| TagValue::Int(vi as i64)),
+                PropColumn::Double(v) => v[i].map(|vi| TagValue::Double(vi.to_bits())),
+                PropColumn::Character(v) => v[i].clone().map(TagValue::String),
+            };
+
+            let Some(value) = value else {
+                continue;
+            };
+
+            let k = *key_index.entry(name.as_str()).or_insert_with(|| {
+                keys.push(name.as_str());
+                keys.len() as u32 - 1
+            });
+
+            let v = *value_index.entry(value.clone()).or_insert_with(|| {
+                values.push(value);
+                values.len() as u32 - 1
+            });
+
+            tags.push(k);
+            tags.push(v);
+        }
+
+        // ids are the 1-based index of the feature in `x`
+        let mut feat = PbfWriter::default();
+        feat.uint(1, i as u64 + 1);
+        if !tags.is_empty() {
+            feat.packed(2, &tags);
+        }
+        feat.uint(3, geom_type as u64);
+        feat.packed(4, &cmds);
+
+        lyr.bytes(2, &feat.0);
+    }
+
+    for k in keys {
+        lyr.bytes(3, k.as_bytes());
+    }
+
+    for v in values {
+        let mut val = PbfWriter::default();
+        match v {
+            TagValue::String(s) => val.bytes(1, s.as_bytes()),
+            TagValue::Double(bits) => val.double(3, f64::from_bits(bits)),
+            TagValue::Int(i) => val.sint(6, i),
+            TagValue::Bool(b) => val.uint(7, b as u64),
+        }
+        lyr.bytes(4, &val.0);
+    }
+
+    lyr.uint(5, extent as u64);
+
+    let mut tile = PbfWriter::default();
+    tile.bytes(3, &lyr.0);
+
+    Raw::from_bytes(&tile.0)
+}
+
+extendr_module! {
+    mod mvtimpl;
+    fn mvt_encode_;
+}
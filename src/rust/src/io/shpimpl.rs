@@ -0,0 +1,354 @@
+use extendr_api::prelude::*;
+use geo::orient::{Direction, Orient};
+use geo::Contains;
+use geo_types::{
+    Coord, Geometry, LineString, MultiLineString, MultiPoint, MultiPolygon, Point, Polygon,
+};
+use geojson::{JsonObject, JsonValue};
+use sfconversions::{geometry_from_list, vctrs::rsgeo_type};
+use shapefile::dbase::{self, FieldName, FieldType, FieldValue, Record, TableWriterBuilder};
+use shapefile::{PolygonRing, Shape};
+use std::io::{Read, Seek, SeekFrom};
+
+use super::geojsonimpl::{process_properties, PropColumn};
+use crate::utils::geoms_to_rsgeo;
+
+#[extendr]
+fn read_shp_(file: &str) -> List {
+    let shapes = shapefile::ShapeReader::from_path(file)
+        .unwrap_or_else(|e| panic!("unable to read shapefile `{file}`: {e}"));
+
+    let dbf = std::path::Path::new(file).with_extension("dbf");
+    let mut source = std::fs::File::open(&dbf)
+        .map(std::io::BufReader::new)
+        .unwrap_or_else(|e| panic!("unable to read `{}`: {e}", dbf.display()));
+    let decimals = dbf_decimals(&mut source)
+        .unwrap_or_else(|e| panic!("unable to read `{}`: {e}", dbf.display()));
+    let table = dbase::Reader::new(source)
+        .unwrap_or_else(|e| panic!("unable to read `{}`: {e}", dbf.display()));
+
+    // dbase records are unordered so the field order is taken from the header
+    // numeric fields without decimals are read as integers when they fit
+    let fields = table
+        .fields()
+        .iter()
+        .zip(decimals)
+        .map(|(f, d)| {
+            let integer = matches!(f.field_type(), FieldType::Numeric) && d == 0;
+            (f.name().to_string(), integer)
+        })
+        .collect::<Vec<(String, bool)>>();
+
+    let mut reader = shapefile::Reader::new(shapes, table);
+
+    let (geoms, props): (Vec<Option<Geometry>>, Vec<Option<JsonObject>>) = reader
+        .iter_shapes_and_records()
+        .enumerate()
+        .map(|(i, res)| {
+            let (shape, record) =
+                res.unwrap_or_else(|e| panic!("failed to read record {}: {e}", i + 1));
+
+            let props = fields
+                .iter()
+                .map(|(name, integer)| {
+                    let value = record
+                        .get(name)
+                        .map(|v| field_value(v, *integer))
+                        .unwrap_or(JsonValue::Null);
+                    (name.to_owned(), value)
+                })
+                .collect::<JsonObject>();
+
+            (shape_to_geometry(shape), Some(props))
+        })
+        .unzip();
+
+    list!(
+        geometry = geoms_to_rsgeo(unify_multi(geoms)),
+        properties = process_properties(props)
+    )
+}
+
+// dbase does not expose the decimal places of a field so they are read from
+// the field descriptors of the header before rewinding for dbase::Reader
+fn dbf_decimals<R: Read + Seek>(x: &mut R) -> std::io::Result<Vec<u8>> {
+    let mut header = [0u8; 32];
+    x.read_exact(&mut header)?;
+
+    let mut res = Vec::new();
+    let mut descriptor = [0u8; 32];
+    loop {
+        x.read_exact(&mut descriptor[..1])?;
+        // the descriptor array is terminated by a carriage return
+        if descriptor[0] == 0x0D {
+            break;
+        }
+        x.read_exact(&mut descriptor[1..])?;
+        res.push(descriptor[17]);
+    }
+
+    x.seek(SeekFrom::Start(0))?;
+    Ok(res)
+}
+
+fn field_value(x: &FieldValue, integer: bool) -> JsonValue {
+    match x {
+        FieldValue::Character(v) => v.as_deref().map(JsonValue::from).unwrap_or(JsonValue::Null),
+        FieldValue::Numeric(Some(v))
+            if integer && *v > i32::MIN as f64 && *v <= i32::MAX as f64 =>
+        {
+            JsonValue::from(*v as i64)
+        }
+        FieldValue::Numeric(v) => v.map(JsonValue::from).unwrap_or(JsonValue::Null),
+        FieldValue::Logical(v) => v.map(JsonValue::from).unwrap_or(JsonValue::Null),
+        FieldValue::Float(v) => v.map(|f| JsonValue::from(f as f64)).unwrap_or(JsonValue::Null),
+        FieldValue::Integer(v) => JsonValue::from(*v),
+        FieldValue::Currency(v) | FieldValue::Double(v) => JsonValue::from(*v),
+        FieldValue::Memo(v) => JsonValue::from(v.as_str()),
+        FieldValue::Date(v) => v
+            .as_ref()
+            .map(|d| JsonValue::from(format!("{:04}-{:02}-{:02}", d.year(), d.month(), d.day())))
+            .unwrap_or(JsonValue::Null),
+        FieldValue::DateTime(v) => {
+            let (d, t) = (v.date(), v.time());
+            JsonValue::from(format!(
+                "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                d.year(),
+                d.month(),
+                d.day(),
+                t.hours(),
+                t.minutes(),
+                t.seconds()
+            ))
+        }
+    }
+}
+
+// Z and M values are dropped
+fn shape_to_geometry(x: Shape) -> Option<Geometry> {
+    let res = match x {
+        Shape::NullShape => return None,
+        Shape::Point(p) => Geometry::Point(Point::new(p.x, p.y)),
+        Shape::PointM(p) => Geometry::Point(Point::new(p.x, p.y)),
+        Shape::PointZ(p) => Geometry::Point(Point::new(p.x, p.y)),
+        Shape::Multipoint(mp) => multipoint(mp.points(), |p| Coord { x: p.x, y: p.y }),
+        Shape::MultipointM(mp) => multipoint(mp.points(), |p| Coord { x: p.x, y: p.y }),
+        Shape::MultipointZ(mp) => multipoint(mp.points(), |p| Coord { x: p.x, y: p.y }),
+        Shape::Polyline(l) => polyline(l.parts(), |p| Coord { x: p.x, y: p.y }),
+        Shape::PolylineM(l) => polyline(l.parts(), |p| Coord { x: p.x, y: p.y }),
+        Shape::PolylineZ(l) => polyline(l.parts(), |p| Coord { x: p.x, y: p.y }),
+        Shape::Polygon(p) => polygon(p.rings(), |p| Coord { x: p.x, y: p.y }),
+        Shape::PolygonM(p) => polygon(p.rings(), |p| Coord { x: p.x, y: p.y }),
+        Shape::PolygonZ(p) => polygon(p.rings(), |p| Coord { x: p.x, y: p.y }),
+        Shape::Multipatch(_) => panic!("Multipatch shapes are not supported"),
+    };
+
+    Some(res)
+}
+
+fn multipoint<P>(x: &[P], f: impl Fn(&P) -> Coord) -> Geometry {
+    Geometry::MultiPoint(MultiPoint::new(x.iter().map(|p| Point(f(p))).collect()))
+}
+
+fn polyline<P>(x: &[Vec<P>], f: impl Fn(&P) -> Coord) -> Geometry {
+    let lines = x
+        .iter()
+        .map(|part| LineString::new(part.iter().map(&f).collect()))
+        .collect();
+
+    Geometry::MultiLineString(MultiLineString::new(lines))
+}
+
+// Shapefile outer rings are clockwise and inner rings counter-clockwise.
+// Holes are assigned to the outer ring that contains them and the result
+// is oriented so that exteriors are counter-clockwise.
+fn polygon<P>(x: &[PolygonRing<P>], f: impl Fn(&P) -> Coord) -> Geometry {
+    let mut polys = Vec::new();
+    let mut holes = Vec::new();
+
+    for ring in x {
+        let ls = LineString::new(ring.points().iter().map(&f).collect());
+        match ring {
+            PolygonRing::Outer(_) => polys.push(Polygon::new(ls, vec![])),
+            PolygonRing::Inner(_) => holes.push(ls),
+        }
+    }
+
+    if polys.is_empty() {
+        polys = holes
+            .drain(..)
+            .map(|h| Polygon::new(h, vec![]))
+            .collect();
+    }
+
+    for hole in holes {
+        let idx = hole
+            .0
+            .first()
+            .and_then(|c| polys.iter().position(|p| p.contains(&Point(*c))))
+            .unwrap_or(polys.len() - 1);
+
+        polys[idx].interiors_push(hole);
+    }
+
+    Geometry::MultiPolygon(MultiPolygon::new(polys).orient(Direction::Default))
+}
+
+// Shapefiles do not distinguish single and multi part geometries. If every
+// geometry has a single part they are returned as single part geometries
+pub fn unify_multi(x: Vec<Option<Geometry>>) -> Vec<Option<Geometry>> {
+    let single = x.iter().flatten().all(|g| match g {
+        Geometry::MultiLineString(ml) => ml.0.len() == 1,
+        Geometry::MultiPolygon(mp) => mp.0.len() == 1,
+        _ => true,
+    });
+
+    if !single {
+        return x;
+    }
+
+    x.into_iter()
+        .map(|xi| {
+            xi.map(|g| match g {
+                Geometry::MultiLineString(mut ml) => Geometry::LineString(ml.0.remove(0)),
+                Geometry::MultiPolygon(mut mp) => Geometry::Polygon(mp.0.remove(0)),
+                g => g,
+            })
+        })
+        .collect()
+}
+
+#[extendr]
+fn write_shp_(x: List, properties: List, file: &str) {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let n = x.len();
+
+    let cols = properties
+        .iter()
+        .map(|(name, col)| (name.to_string(), PropColumn::from_robj(name, col)))
+        .collect::<Vec<(String, PropColumn)>>();
+
+    if cols.iter().any(|(_, col)| col.len() != n) {
+        panic!("`properties` must have the same number of rows as the length of `x`")
+    }
+
+    let geom_type = rsgeo_type(&x);
+    let geoms = geometry_from_list(x);
+
+    if let Some(i) = geoms.iter().position(|g| g.is_none()) {
+        panic!("missing geometries cannot be written, found at element {}", i + 1)
+    }
+
+    let mut table = TableWriterBuilder::new();
+
+    for (name, col) in cols.iter() {
+        let field_name = FieldName::try_from(name.as_str())
+            .unwrap_or_else(|_| panic!("`{name}` is not a valid dBase field name (max 10 characters)"));
+
+        table = match col {
+            PropColumn::Logical(_) => table.add_logical_field(field_name),
+            PropColumn::Integer(_) => table.add_numeric_field(field_name, 11, 0),
+            PropColumn::Double(_) => table.add_numeric_field(field_name, 24, 15),
+            PropColumn::Character(v) => {
+                let width = v
+                    .iter()
+                    .flatten()
+                    .map(|s| s.len())
+                    .max()
+                    .unwrap_or(1)
+                    .clamp(1, 254);
+                table.add_character_field(field_name, width as u8)
+            }
+        };
+    }
+
+    let records = (0..n)
+        .map(|i| {
+            let mut record = Record::default();
+            for (name, col) in cols.iter() {
+                let value = match col {
+                    PropColumn::Logical(v) => FieldValue::Logical(v[i]),
+                    PropColumn::Integer(v) => FieldValue::Numeric(v[i].map(|vi| vi as f64)),
+                    PropColumn::Double(v) => FieldValue::Numeric(v[i]),
+                    PropColumn::Character(v) => FieldValue::Character(v[i].clone()),
+                };
+                record.insert(name.to_owned(), value);
+            }
+            record
+        })
+        .collect::<Vec<Record>>();
+
+    let mut writer = shapefile::Writer::from_path(file, table)
+        .unwrap_or_else(|e| panic!("unable to create `{file}`: {e}"));
+
+    let geoms = geoms.into_iter().flatten();
+
+    // a shapefile has a single shape type so every geometry is converted to it
+    macro_rules! write_shapes {
+        ($shapes:expr) => {
+            for (i, (shape, record)) in $shapes.zip(records.iter()).enumerate() {
+                writer
+                    .write_shape_and_record(&shape, record)
+                    .unwrap_or_else(|e| panic!("failed to write record {}: {e}", i + 1));
+            }
+        };
+    }
+
+    match geom_type.as_str() {
+        "point" => write_shapes!(geoms.map(|g| match g {
+            Geometry::Point(p) => shapefile::Point::new(p.x(), p.y()),
+            _ => unreachable!(),
+        })),
+        "multipoint" => write_shapes!(geoms.map(|g| match g {
+            Geometry::MultiPoint(mp) => shapefile::Multipoint::new(
+                mp.0.iter().map(|p| shapefile::Point::new(p.x(), p.y())).collect(),
+            ),
+            _ => unreachable!(),
+        })),
+        "linestring" | "multilinestring" => write_shapes!(geoms.map(|g| {
+            let parts = match g {
+                Geometry::LineString(l) => vec![l],
+                Geometry::MultiLineString(ml) => ml.0,
+                _ => unreachable!(),
+            };
+            shapefile::Polyline::with_parts(parts.iter().map(shp_points).collect())
+        })),
+        "polygon" | "multipolygon" => write_shapes!(geoms.map(|g| {
+            let polys = match g {
+                Geometry::Polygon(p) => vec![p],
+                Geometry::MultiPolygon(mp) => mp.0,
+                _ => unreachable!(),
+            };
+
+            // rings are reordered to the shapefile orientation when created
+            let rings = polys
+                .iter()
+                .flat_map(|p| {
+                    std::iter::once(PolygonRing::Outer(shp_points(p.exterior()))).chain(
+                        p.interiors()
+                            .iter()
+                            .map(|r| PolygonRing::Inner(shp_points(r))),
+                    )
+                })
+                .collect();
+
+            shapefile::Polygon::with_rings(rings)
+        })),
+        _ => panic!("`{geom_type}` geometries cannot be written to a shapefile"),
+    }
+}
+
+fn shp_points(x: &LineString) -> Vec<shapefile::Point> {
+    x.0.iter()
+        .map(|c| shapefile::Point::new(c.x, c.y))
+        .collect()
+}
+
+extendr_module! {
+    mod shpimpl;
+    fn read_shp_;
+    fn write_shp_;
+}
@@ -0,0 +1,256 @@
+use extendr_api::prelude::*;
+use geo::BoundingRect;
+use geo_types::{coord, Geometry, LineString, Polygon, Rect};
+use sfconversions::Geom;
+use std::fmt::Write;
+
+// maps geometry coordinates into the svg's coordinate space
+struct Viewport {
+    rect: Rect,
+    flip_y: bool,
+    // size of one pixel in user units
+    px: f64,
+}
+
+impl Viewport {
+    fn y(&self, y: f64) -> f64 {
+        if self.flip_y {
+            self.rect.min().y + self.rect.max().y - y
+        } else {
+            y
+        }
+    }
+
+    fn line_path(&self, d: &mut String, x: &LineString, close: bool) {
+        for (i, c) in x.0.iter().enumerate() {
+            let cmd = if i == 0 { 'M' } else { 'L' };
+            write!(d, "{cmd}{} {} ", c.x, self.y(c.y)).unwrap();
+        }
+
+        if close && !x.0.is_empty() {
+            d.push_str("Z ");
+        }
+    }
+
+    fn polygon_path(&self, d: &mut String, x: &Polygon) {
+        self.line_path(d, x.exterior(), true);
+        x.interiors()
+            .iter()
+            .for_each(|r| self.line_path(d, r, true));
+    }
+}
+
+fn escape(x: &str) -> String {
+    x.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn path(out: &mut String, d: String, filled: bool) {
+    if d.is_empty() {
+        return;
+    }
+
+    let d = d.trim_end();
+
+    if filled {
+        writeln!(
+            out,
+            r#"<path fill-rule="evenodd" vector-effect="non-scaling-stroke" d="{d}"/>"#
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            out,
+            r#"<path fill="none" vector-effect="non-scaling-stroke" d="{d}"/>"#
+        )
+        .unwrap();
+    }
+}
+
+fn render(out: &mut String, x: &Geometry, vp: &Viewport, radius: f64) {
+    let mut d = String::new();
+
+    match x {
+        Geometry::Point(p) => {
+            writeln!(
+                out,
+                r#"<circle vector-effect="non-scaling-stroke" cx="{}" cy="{}" r="{radius}"/>"#,
+                p.x(),
+                vp.y(p.y())
+            )
+            .unwrap();
+        }
+        Geometry::MultiPoint(mp) => {
+            mp.0.iter()
+                .for_each(|p| render(out, &Geometry::Point(*p), vp, radius))
+        }
+        Geometry::Line(l) => {
+            vp.line_path(&mut d, &LineString::from(*l), false);
+            path(out, d, false);
+        }
+        Geometry::LineString(l) => {
+            vp.line_path(&mut d, l, false);
+            path(out, d, false);
+        }
+        Geometry::MultiLineString(ml) => {
+            ml.0.iter().for_each(|l| vp.line_path(&mut d, l, false));
+            path(out, d, false);
+        }
+        Geometry::Polygon(p) => {
+            vp.polygon_path(&mut d, p);
+            path(out, d, true);
+        }
+        Geometry::MultiPolygon(mp) => {
+            mp.0.iter().for_each(|p| vp.polygon_path(&mut d, p));
+            path(out, d, true);
+        }
+        Geometry::Rect(r) => {
+            vp.polygon_path(&mut d, &r.to_polygon());
+            path(out, d, true);
+        }
+        Geometry::Triangle(t) => {
+            vp.polygon_path(&mut d, &t.to_polygon());
+            path(out, d, true);
+        }
+        Geometry::GeometryCollection(gc) => gc.0.iter().for_each(|g| render(out, g, vp, radius)),
+    }
+}
+
+// recycles length one vectors
+fn recycled<T: Copy>(x: &[T], i: usize) -> T {
+    if x.len() == 1 {
+        x[0]
+    } else {
+        x[i]
+    }
+}
+
+#[extendr]
+#[allow(clippy::too_many_arguments)]
+fn svg_from_geoms_(
+    x: List,
+    fill: Strings,
+    stroke: Strings,
+    stroke_width: Doubles,
+    point_radius: Doubles,
+    width: f64,
+    height: f64,
+    flip_y: bool,
+    padding: f64,
+) -> String {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let n = x.len();
+
+    for (arg, len) in [
+        ("fill", fill.len()),
+        ("stroke", stroke.len()),
+        ("stroke_width", stroke_width.len()),
+        ("point_radius", point_radius.len()),
+    ] {
+        if len != 1 && len != n {
+            panic!("`{arg}` must be length 1 or the same length as `x`")
+        }
+    }
+
+    let robjs = x.iter().map(|(_, xi)| xi).collect::<Vec<Robj>>();
+    let geoms = robjs
+        .iter()
+        .map(|xi| <&Geom>::try_from(xi).ok().map(|g| &g.geom))
+        .collect::<Vec<Option<&Geometry>>>();
+
+    // the viewport is fit to the bounding box of all geometries
+    let bbox = geoms
+        .iter()
+        .flatten()
+        .filter_map(|g| g.bounding_rect())
+        .reduce(|a, b| {
+            Rect::new(
+                coord! { x: a.min().x.min(b.min().x), y: a.min().y.min(b.min().y) },
+                coord! { x: a.max().x.max(b.max().x), y: a.max().y.max(b.max().y) },
+            )
+        })
+        .unwrap_or(Rect::new(
+            coord! { x: 0.0, y: 0.0 },
+            coord! { x: 1.0, y: 1.0 },
+        ));
+
+    // a single point has no extent so it is centered in a unit square
+    let span = bbox.width().max(bbox.height());
+    let pad = if span == 0.0 { 0.5 } else { span * padding };
+
+    let rect = Rect::new(
+        coord! { x: bbox.min().x - pad, y: bbox.min().y - pad },
+        coord! { x: bbox.max().x + pad, y: bbox.max().y + pad },
+    );
+
+    let vp = Viewport {
+        rect,
+        flip_y,
+        px: (rect.width() / width).max(rect.height() / height),
+    };
+
+    let mut out = String::new();
+
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}" viewBox="{} {} {} {}">"#,
+        rect.min().x,
+        rect.min().y,
+        rect.width(),
+        rect.height()
+    )
+    .unwrap();
+
+    let fill = fill.iter().collect::<Vec<Rstr>>();
+    let stroke = stroke.iter().collect::<Vec<Rstr>>();
+    let stroke_width = stroke_width
+        .iter()
+        .map(|xi| xi.inner())
+        .collect::<Vec<f64>>();
+    let point_radius = point_radius
+        .iter()
+        .map(|xi| xi.inner())
+        .collect::<Vec<f64>>();
+
+    for (i, g) in geoms.iter().enumerate() {
+        let Some(g) = g else {
+            continue;
+        };
+
+        let style = |x: &[Rstr]| {
+            let xi = if x.len() == 1 { &x[0] } else { &x[i] };
+            if xi.is_na() {
+                "none".to_string()
+            } else {
+                escape(xi.as_str())
+            }
+        };
+
+        // strokes are sized in pixels regardless of the viewport's scale
+        writeln!(
+            out,
+            r#"<g fill="{}" stroke="{}" stroke-width="{}">"#,
+            style(&fill),
+            style(&stroke),
+            recycled(&stroke_width, i),
+        )
+        .unwrap();
+
+        render(&mut out, g, &vp, recycled(&point_radius, i) * vp.px);
+
+        out.push_str("</g>\n");
+    }
+
+    out.push_str("</svg>\n");
+    out
+}
+
+extendr_module! {
+    mod svgimpl;
+    fn svg_from_geoms_;
+}
@@ -1,68 +1,441 @@
 use extendr_api::prelude::*;
-use wkb::*;
+use geo_types::{
+    Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon,
+    Point, Polygon,
+};
+use rayon::prelude::*;
+use sfconversions::geometry_from_list;
 
-use crate::to_pntr;
-use crate::utils::determine_geoms_class;
-use crate::{geoms::from_list, types::Geom};
+use crate::utils::geoms_to_rsgeo;
+
+// WKB geometry type codes
+const WKB_POINT: u32 = 1;
+const WKB_LINESTRING: u32 = 2;
+const WKB_POLYGON: u32 = 3;
+const WKB_MULTIPOINT: u32 = 4;
+const WKB_MULTILINESTRING: u32 = 5;
+const WKB_MULTIPOLYGON: u32 = 6;
+const WKB_GEOMETRYCOLLECTION: u32 = 7;
+
+// EWKB flags stored in the high bits of the geometry type
+const EWKB_Z: u32 = 0x80000000;
+const EWKB_M: u32 = 0x40000000;
+const EWKB_SRID: u32 = 0x20000000;
+
+// The smallest encoding of a geometry is a byte order, a type, and an element
+// count. A point is a byte order, a type, and two coordinates.
+const WKB_MIN_SIZE: usize = 9;
+const WKB_MIN_POINT_SIZE: usize = 21;
+
+// Geometry collections may contain collections so the nesting is limited to
+// keep malformed input from overflowing the stack
+const WKB_MAX_DEPTH: usize = 32;
 
 #[extendr]
-/// WKB Translation
-///
-/// @export
-/// @rdname wkb
-fn wkb_from_geom(x: Robj) -> Vec<u8> {
-    let x = Geom::from(x).geom;
-    geom_to_wkb(&x).unwrap()
+fn wkb_from_geoms_(x: List, little_endian: bool, srid: Integers) -> List {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let srid = match srid.iter().next() {
+        Some(s) if !s.is_na() => Some(s.inner()),
+        _ => None,
+    };
+
+    let res_vec = geometry_from_list(x)
+        .into_par_iter()
+        .map(|xi| xi.map(|g| geometry_to_wkb(&g, little_endian, srid)))
+        .collect::<Vec<Option<Vec<u8>>>>();
+
+    let res = res_vec
+        .into_iter()
+        .map(|xi| match xi {
+            Some(bytes) => Raw::from_bytes(&bytes).into_robj(),
+            None => NULL.into_robj(),
+        })
+        .collect::<Vec<Robj>>();
+
+    List::from_values(res)
 }
 
 #[extendr]
-/// @export
-/// @rdname wkb
-fn wkb_from_geoms(x: List) -> List {
-    let x = from_list(x);
-    x.into_iter()
-        .map(|x| geom_to_wkb(&x.geom).unwrap())
-        .collect::<List>()
+fn wkb_to_geoms_(x: List) -> Robj {
+    let bytes = x
+        .iter()
+        .enumerate()
+        .map(|(i, (_, xi))| {
+            if xi.is_null() {
+                None
+            } else {
+                match Raw::try_from(xi) {
+                    Ok(r) => Some(r.as_slice().to_vec()),
+                    Err(_) => panic!("element {} is not a raw vector", i + 1),
+                }
+            }
+        })
+        .collect::<Vec<Option<Vec<u8>>>>();
+
+    let res_vec = bytes
+        .into_par_iter()
+        .map(|xi| match xi {
+            Some(b) => wkb_to_geometry(&b).map(Some),
+            None => Ok(None),
+        })
+        .collect::<Vec<std::result::Result<Option<(Option<Geometry>, Option<i32>)>, String>>>();
+
+    let mut srids: Vec<Option<i32>> = Vec::new();
+    let mut geoms: Vec<Option<Geometry>> = Vec::with_capacity(res_vec.len());
+
+    for (i, xi) in res_vec.into_iter().enumerate() {
+        match xi {
+            Ok(Some((g, srid))) => {
+                srids.push(srid);
+                geoms.push(g);
+            }
+            Ok(None) => geoms.push(None),
+            Err(e) => panic!("failed to parse WKB at element {}: {e}", i + 1),
+        }
+    }
+
+    let mut res = geoms_to_rsgeo(geoms);
+
+    // the SRID is only kept when every geometry shares it so that it can be
+    // written back out
+    if let Some(Some(srid)) = srids.first() {
+        if srids.iter().all(|s| s == &Some(*srid)) {
+            res.set_attrib("srid", *srid).unwrap();
+        }
+    }
+
+    res
 }
 
-use std::io::Cursor;
+// Encoding ---------------------------------------------------------------
 
-#[extendr(use_try_from = true)]
-/// @export
-/// @rdname wkb
-fn wkb_to_geom(x: Vec<u8>) -> Robj {
-    let mut bytes_cursor = Cursor::new(x);
-    let p = bytes_cursor.read_wkb().unwrap();
-    to_pntr(Geom::from(p))
+struct WkbWriter {
+    buf: Vec<u8>,
+    little_endian: bool,
 }
 
-#[extendr(use_try_from = true)]
-/// @export
-/// @rdname wkb
-fn wkb_to_geoms(x: List) -> Robj {
-    let res = x
-        .into_iter()
-        .map(|(_, x)| wkb_to_geom(raw_to_vecu8(x)))
-        .collect::<List>()
-        .into_robj();
+impl WkbWriter {
+    fn header(&mut self, geom_type: u32) {
+        self.buf.push(self.little_endian as u8);
+        self.u32(geom_type);
+    }
+
+    fn u32(&mut self, x: u32) {
+        if self.little_endian {
+            self.buf.extend_from_slice(&x.to_le_bytes());
+        } else {
+            self.buf.extend_from_slice(&x.to_be_bytes());
+        }
+    }
+
+    fn coord(&mut self, x: &Coord) {
+        if self.little_endian {
+            self.buf.extend_from_slice(&x.x.to_le_bytes());
+            self.buf.extend_from_slice(&x.y.to_le_bytes());
+        } else {
+            self.buf.extend_from_slice(&x.x.to_be_bytes());
+            self.buf.extend_from_slice(&x.y.to_be_bytes());
+        }
+    }
+
+    fn ring(&mut self, x: &LineString) {
+        self.u32(x.0.len() as u32);
+        x.0.iter().for_each(|c| self.coord(c));
+    }
+
+    fn polygon_body(&mut self, x: &Polygon) {
+        // an empty exterior ring is an empty polygon
+        if x.exterior().0.is_empty() {
+            self.u32(0);
+            return;
+        }
+        self.u32(x.interiors().len() as u32 + 1);
+        self.ring(x.exterior());
+        x.interiors().iter().for_each(|r| self.ring(r));
+    }
 
-    res.set_attrib("class", determine_geoms_class(&res))
-        .unwrap()
+    fn geometry(&mut self, x: &Geometry, srid: Option<i32>) {
+        // the SRID flag and value are only written for the outer geometry
+        let flag = |code: u32| match srid {
+            Some(_) => code | EWKB_SRID,
+            None => code,
+        };
+
+        let code = match x {
+            Geometry::Point(_) => WKB_POINT,
+            Geometry::Line(_) | Geometry::LineString(_) => WKB_LINESTRING,
+            Geometry::Polygon(_) | Geometry::Rect(_) | Geometry::Triangle(_) => WKB_POLYGON,
+            Geometry::MultiPoint(_) => WKB_MULTIPOINT,
+            Geometry::MultiLineString(_) => WKB_MULTILINESTRING,
+            Geometry::MultiPolygon(_) => WKB_MULTIPOLYGON,
+            Geometry::GeometryCollection(_) => WKB_GEOMETRYCOLLECTION,
+        };
+
+        self.header(flag(code));
+
+        if let Some(s) = srid {
+            self.u32(s as u32);
+        }
+
+        match x {
+            Geometry::Point(p) => self.coord(&p.0),
+            Geometry::Line(l) => self.ring(&LineString::from(*l)),
+            Geometry::LineString(l) => self.ring(l),
+            Geometry::Polygon(p) => self.polygon_body(p),
+            Geometry::Rect(r) => self.polygon_body(&r.to_polygon()),
+            Geometry::Triangle(t) => self.polygon_body(&t.to_polygon()),
+            Geometry::MultiPoint(mp) => {
+                self.u32(mp.0.len() as u32);
+                mp.0.iter()
+                    .for_each(|p| self.geometry(&Geometry::Point(*p), None));
+            }
+            Geometry::MultiLineString(ml) => {
+                self.u32(ml.0.len() as u32);
+                ml.0.iter().for_each(|l| {
+                    self.header(WKB_LINESTRING);
+                    self.ring(l);
+                });
+            }
+            Geometry::MultiPolygon(mp) => {
+                self.u32(mp.0.len() as u32);
+                mp.0.iter().for_each(|p| {
+                    self.header(WKB_POLYGON);
+                    self.polygon_body(p);
+                });
+            }
+            Geometry::GeometryCollection(gc) => {
+                self.u32(gc.0.len() as u32);
+                gc.0.iter().for_each(|g| self.geometry(g, None));
+            }
+        }
+    }
 }
 
-fn raw_to_vecu8(x: Robj) -> Vec<u8> {
-    let x = extendr_api::Raw::try_from(x).unwrap();
-    x.as_slice()
-        .iter()
-        .map(|x| x.to_owned())
-        .collect::<Vec<u8>>()
+// Encode a geometry as WKB. When an SRID is provided EWKB is written.
+pub fn geometry_to_wkb(x: &Geometry, little_endian: bool, srid: Option<i32>) -> Vec<u8> {
+    let mut writer = WkbWriter {
+        buf: Vec::new(),
+        little_endian,
+    };
+    writer.geometry(x, srid);
+    writer.buf
+}
+
+// Decoding ---------------------------------------------------------------
+
+struct WkbReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+// dimensions are tracked so that Z and M values can be skipped
+#[derive(Clone, Copy)]
+struct WkbHeader {
+    little_endian: bool,
+    code: u32,
+    n_dim: usize,
+    srid: Option<i32>,
+}
+
+impl<'a> WkbReader<'a> {
+    fn take<const N: usize>(&mut self) -> std::result::Result<[u8; N], String> {
+        let end = self.pos + N;
+        if end > self.buf.len() {
+            return Err(String::from("unexpected end of WKB"));
+        }
+        let mut out = [0u8; N];
+        out.copy_from_slice(&self.buf[self.pos..end]);
+        self.pos = end;
+        Ok(out)
+    }
+
+    fn u32(&mut self, little_endian: bool) -> std::result::Result<u32, String> {
+        let b = self.take::<4>()?;
+        Ok(if little_endian {
+            u32::from_le_bytes(b)
+        } else {
+            u32::from_be_bytes(b)
+        })
+    }
+
+    fn f64(&mut self, little_endian: bool) -> std::result::Result<f64, String> {
+        let b = self.take::<8>()?;
+        Ok(if little_endian {
+            f64::from_le_bytes(b)
+        } else {
+            f64::from_be_bytes(b)
+        })
+    }
+
+    // The capacity for `n` elements each at least `min_size` bytes. The count
+    // is read from the input so it is capped by the bytes that remain.
+    fn capacity(&self, n: u32, min_size: usize) -> usize {
+        (n as usize).min((self.buf.len() - self.pos) / min_size)
+    }
+
+    fn header(&mut self) -> std::result::Result<WkbHeader, String> {
+        let little_endian = match self.take::<1>()?[0] {
+            0 => false,
+            1 => true,
+            b => return Err(format!("invalid byte order {b}")),
+        };
+
+        let raw = self.u32(little_endian)?;
+
+        let mut n_dim = 2;
+        if raw & EWKB_Z != 0 {
+            n_dim += 1;
+        }
+        if raw & EWKB_M != 0 {
+            n_dim += 1;
+        }
+
+        let srid = if raw & EWKB_SRID != 0 {
+            Some(self.u32(little_endian)? as i32)
+        } else {
+            None
+        };
+
+        // ISO WKB encodes dimensions as multiples of 1000
+        let code = raw & 0x0000ffff;
+        let (code, n_dim) = match code / 1000 {
+            0 => (code, n_dim),
+            1 | 2 => (code % 1000, 3),
+            3 => (code % 1000, 4),
+            _ => return Err(format!("unknown geometry type {raw}")),
+        };
+
+        Ok(WkbHeader {
+            little_endian,
+            code,
+            n_dim,
+            srid,
+        })
+    }
+
+    fn coord(&mut self, h: &WkbHeader) -> std::result::Result<Coord, String> {
+        let x = self.f64(h.little_endian)?;
+        let y = self.f64(h.little_endian)?;
+        for _ in 2..h.n_dim {
+            self.f64(h.little_endian)?;
+        }
+        Ok(Coord { x, y })
+    }
+
+    fn ring(&mut self, h: &WkbHeader) -> std::result::Result<LineString, String> {
+        let n = self.u32(h.little_endian)?;
+        let crds = (0..n)
+            .map(|_| self.coord(h))
+            .collect::<std::result::Result<Vec<Coord>, String>>()?;
+        Ok(LineString::new(crds))
+    }
+
+    fn polygon(&mut self, h: &WkbHeader) -> std::result::Result<Polygon, String> {
+        let n = self.u32(h.little_endian)?;
+        let mut rings = (0..n)
+            .map(|_| self.ring(h))
+            .collect::<std::result::Result<Vec<LineString>, String>>()?
+            .into_iter();
+
+        let ext = rings.next().unwrap_or(LineString::new(vec![]));
+        Ok(Polygon::new(ext, rings.collect()))
+    }
+
+    fn geometry(&mut self) -> std::result::Result<(Option<Geometry>, Option<i32>), String> {
+        if self.depth == WKB_MAX_DEPTH {
+            return Err(format!(
+                "geometries are nested more than {WKB_MAX_DEPTH} levels deep"
+            ));
+        }
+
+        self.depth += 1;
+        let res = self.geometry_body();
+        self.depth -= 1;
+        res
+    }
+
+    // empty points have no representation in geo-types so are None
+    fn geometry_body(&mut self) -> std::result::Result<(Option<Geometry>, Option<i32>), String> {
+        let h = self.header()?;
+
+        let geom = match h.code {
+            WKB_POINT => {
+                let crd = self.coord(&h)?;
+                if crd.x.is_nan() && crd.y.is_nan() {
+                    None
+                } else {
+                    Some(Geometry::Point(Point(crd)))
+                }
+            }
+            WKB_LINESTRING => Some(Geometry::LineString(self.ring(&h)?)),
+            WKB_POLYGON => Some(Geometry::Polygon(self.polygon(&h)?)),
+            WKB_MULTIPOINT => {
+                let n = self.u32(h.little_endian)?;
+                let mut pnts = Vec::with_capacity(self.capacity(n, WKB_MIN_POINT_SIZE));
+                for _ in 0..n {
+                    if let (Some(Geometry::Point(p)), _) = self.geometry()? {
+                        pnts.push(p);
+                    }
+                }
+                Some(Geometry::MultiPoint(MultiPoint::new(pnts)))
+            }
+            WKB_MULTILINESTRING => {
+                let n = self.u32(h.little_endian)?;
+                let mut lns = Vec::with_capacity(self.capacity(n, WKB_MIN_SIZE));
+                for _ in 0..n {
+                    match self.geometry()? {
+                        (Some(Geometry::LineString(l)), _) => lns.push(l),
+                        _ => return Err(String::from("expected a LineString")),
+                    }
+                }
+                Some(Geometry::MultiLineString(MultiLineString::new(lns)))
+            }
+            WKB_MULTIPOLYGON => {
+                let n = self.u32(h.little_endian)?;
+                let mut plys = Vec::with_capacity(self.capacity(n, WKB_MIN_SIZE));
+                for _ in 0..n {
+                    match self.geometry()? {
+                        (Some(Geometry::Polygon(p)), _) => plys.push(p),
+                        _ => return Err(String::from("expected a Polygon")),
+                    }
+                }
+                Some(Geometry::MultiPolygon(MultiPolygon::new(plys)))
+            }
+            WKB_GEOMETRYCOLLECTION => {
+                let n = self.u32(h.little_endian)?;
+                let mut geoms = Vec::with_capacity(self.capacity(n, WKB_MIN_SIZE));
+                for _ in 0..n {
+                    if let (Some(g), _) = self.geometry()? {
+                        geoms.push(g);
+                    }
+                }
+                Some(Geometry::GeometryCollection(GeometryCollection::new_from(
+                    geoms,
+                )))
+            }
+            code => return Err(format!("unknown geometry type {code}")),
+        };
+
+        Ok((geom, h.srid))
+    }
+}
+
+// Decode WKB or EWKB returning the geometry and its SRID if present
+pub fn wkb_to_geometry(x: &[u8]) -> std::result::Result<(Option<Geometry>, Option<i32>), String> {
+    let mut reader = WkbReader {
+        buf: x,
+        pos: 0,
+        depth: 0,
+    };
+    reader.geometry()
 }
 
-//extendr_api::raw
 extendr_module! {
     mod wkbimpl;
-    fn wkb_from_geom;
-    fn wkb_from_geoms;
-    fn wkb_to_geom;
-    fn wkb_to_geoms;
+    fn wkb_from_geoms_;
+    fn wkb_to_geoms_;
 }
@@ -0,0 +1,590 @@
+use extendr_api::prelude::*;
+use extendr_api::GetSexp;
+use geo_types::{
+    Coord, Geometry, GeometryCollection, LineString, MultiLineString, MultiPoint, MultiPolygon,
+    Point, Polygon,
+};
+use libR_sys::{
+    R_ClearExternalPtr, R_ExternalPtrAddr, R_MakeExternalPtr, R_NilValue, R_RegisterCFinalizerEx,
+    Rboolean, SEXP,
+};
+use sfconversions::{vctrs::rsgeo_type, Geom};
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int, c_void};
+use std::ptr;
+
+use crate::utils::geoms_to_rsgeo;
+
+// Definitions mirror wk's C API in `wk-v1.h`
+const WK_CONTINUE: c_int = 0;
+const WK_ABORT: c_int = 1;
+const WK_ABORT_FEATURE: c_int = 2;
+
+const WK_PART_ID_NONE: u32 = u32::MAX;
+const WK_SIZE_UNKNOWN: u32 = u32::MAX;
+const WK_SRID_NONE: u32 = u32::MAX;
+const WK_PRECISION_NONE: f64 = 0.0;
+
+const WK_GEOMETRY: u32 = 0;
+const WK_POINT: u32 = 1;
+const WK_LINESTRING: u32 = 2;
+const WK_POLYGON: u32 = 3;
+const WK_MULTIPOINT: u32 = 4;
+const WK_MULTILINESTRING: u32 = 5;
+const WK_MULTIPOLYGON: u32 = 6;
+const WK_GEOMETRYCOLLECTION: u32 = 7;
+
+#[repr(C)]
+struct WkMeta {
+    geometry_type: u32,
+    flags: u32,
+    srid: u32,
+    size: u32,
+    bounds_min: [f64; 4],
+    bounds_max: [f64; 4],
+    precision: f64,
+}
+
+impl WkMeta {
+    fn new(geometry_type: u32, size: u32) -> Self {
+        WkMeta {
+            geometry_type,
+            flags: 0,
+            srid: WK_SRID_NONE,
+            size,
+            bounds_min: [0.0; 4],
+            bounds_max: [0.0; 4],
+            precision: WK_PRECISION_NONE,
+        }
+    }
+}
+
+#[repr(C)]
+struct WkVectorMeta {
+    geometry_type: u32,
+    flags: u32,
+    size: isize,
+    bounds_min: [f64; 4],
+    bounds_max: [f64; 4],
+}
+
+#[repr(C)]
+struct WkHandler {
+    api_version: c_int,
+    dirty: c_int,
+    handler_data: *mut c_void,
+    initialize: unsafe extern "C" fn(*mut c_int, *mut c_void),
+    vector_start: unsafe extern "C" fn(*const WkVectorMeta, *mut c_void) -> c_int,
+    feature_start: unsafe extern "C" fn(*const WkVectorMeta, isize, *mut c_void) -> c_int,
+    null_feature: unsafe extern "C" fn(*mut c_void) -> c_int,
+    geometry_start: unsafe extern "C" fn(*const WkMeta, u32, *mut c_void) -> c_int,
+    ring_start: unsafe extern "C" fn(*const WkMeta, u32, u32, *mut c_void) -> c_int,
+    coord: unsafe extern "C" fn(*const WkMeta, *const f64, u32, *mut c_void) -> c_int,
+    ring_end: unsafe extern "C" fn(*const WkMeta, u32, u32, *mut c_void) -> c_int,
+    geometry_end: unsafe extern "C" fn(*const WkMeta, u32, *mut c_void) -> c_int,
+    feature_end: unsafe extern "C" fn(*const WkVectorMeta, isize, *mut c_void) -> c_int,
+    vector_end: unsafe extern "C" fn(*const WkVectorMeta, *mut c_void) -> SEXP,
+    error: unsafe extern "C" fn(*const c_char, *mut c_void) -> c_int,
+    deinitialize: unsafe extern "C" fn(*mut c_void),
+    finalizer: unsafe extern "C" fn(*mut c_void),
+}
+
+// return early from a handler call unless we should continue
+macro_rules! wk_try {
+    ($x:expr) => {
+        let res = $x;
+        if res != WK_CONTINUE {
+            return res;
+        }
+    };
+}
+
+// Reader -----------------------------------------------------------------
+
+extern "C" {
+    // Defined in src/wk.c
+    fn rsgeo_unwind_protect(
+        fun: unsafe extern "C" fn(*mut c_void) -> SEXP,
+        data: *mut c_void,
+        cont: SEXP,
+        jumped: *mut c_int,
+    ) -> SEXP;
+    fn rsgeo_writer_initialize(dirty: *mut c_int, data: *mut c_void);
+    fn rsgeo_writer_vector_end(meta: *const WkVectorMeta, data: *mut c_void) -> SEXP;
+}
+
+// Called by `rsgeo_wk_handle()` in src/wk.c once the handler is initialized.
+// Reading runs under `R_UnwindProtect()` and a long jump sets `jumped` so that
+// it can be resumed in C after this returns. Returns a null pointer if `x`
+// is not a list of geometries.
+#[no_mangle]
+unsafe extern "C" fn rsgeo_wk_read(
+    x: SEXP,
+    handler: *mut WkHandler,
+    cont: SEXP,
+    jumped: *mut c_int,
+) -> SEXP {
+    unsafe extern "C" fn call(data: *mut c_void) -> SEXP {
+        let (geoms, h, vector_meta) =
+            &*(data as *const (Vec<Option<&Geom>>, &WkHandler, WkVectorMeta));
+        read_rsgeo(geoms, h, vector_meta)
+    }
+
+    let Ok(x) = List::try_from(Robj::from_sexp(x)) else {
+        return ptr::null_mut();
+    };
+
+    let geometry_type = match rsgeo_type(&x).as_str() {
+        "point" => WK_POINT,
+        "linestring" => WK_LINESTRING,
+        "polygon" => WK_POLYGON,
+        "multipoint" => WK_MULTIPOINT,
+        "multilinestring" => WK_MULTILINESTRING,
+        "multipolygon" => WK_MULTIPOLYGON,
+        "geometrycollection" => WK_GEOMETRYCOLLECTION,
+        _ => WK_GEOMETRY,
+    };
+
+    let vector_meta = WkVectorMeta {
+        geometry_type,
+        flags: 0,
+        size: x.len() as isize,
+        bounds_min: [0.0; 4],
+        bounds_max: [0.0; 4],
+    };
+
+    // geometries are borrowed from the list and never copied
+    let elements = x.iter().map(|(_, xi)| xi).collect::<Vec<Robj>>();
+
+    let geoms = elements
+        .iter()
+        .map(|xi| {
+            if xi.is_null() {
+                Some(None)
+            } else {
+                <&Geom>::try_from(xi).ok().map(Some)
+            }
+        })
+        .collect::<Option<Vec<Option<&Geom>>>>();
+
+    let Some(geoms) = geoms else {
+        return ptr::null_mut();
+    };
+
+    // nothing below `call()` owns values that need to be dropped as a long
+    // jump skips over those frames
+    let mut data = (geoms, &*handler, vector_meta);
+    rsgeo_unwind_protect(call, &mut data as *mut _ as *mut c_void, cont, jumped)
+}
+
+unsafe fn read_rsgeo(geoms: &[Option<&Geom>], h: &WkHandler, vector_meta: &WkVectorMeta) -> SEXP {
+    let data = h.handler_data;
+
+    if (h.vector_start)(vector_meta, data) == WK_CONTINUE {
+        for (i, geom) in geoms.iter().enumerate() {
+            let i = i as isize;
+
+            let res = (h.feature_start)(vector_meta, i, data);
+            if res == WK_ABORT_FEATURE {
+                continue;
+            } else if res == WK_ABORT {
+                break;
+            }
+
+            let res = match geom {
+                Some(geom) => read_geometry(&geom.geom, WK_PART_ID_NONE, h),
+                None => (h.null_feature)(data),
+            };
+
+            if res == WK_ABORT_FEATURE {
+                continue;
+            } else if res == WK_ABORT {
+                break;
+            }
+
+            if (h.feature_end)(vector_meta, i, data) == WK_ABORT {
+                break;
+            }
+        }
+    }
+
+    (h.vector_end)(vector_meta, data)
+}
+
+// Reading runs under `R_UnwindProtect()` so nothing below may own values that
+// need to be dropped. Geometries are passed as coordinate slices rather than
+// converted.
+
+unsafe fn read_coords(x: &[Coord], meta: &WkMeta, h: &WkHandler) -> c_int {
+    for (i, crd) in x.iter().enumerate() {
+        let xy = [crd.x, crd.y];
+        wk_try!((h.coord)(meta, xy.as_ptr(), i as u32, h.handler_data));
+    }
+    WK_CONTINUE
+}
+
+unsafe fn read_point(x: &Point, part_id: u32, h: &WkHandler) -> c_int {
+    let data = h.handler_data;
+    let meta = WkMeta::new(WK_POINT, 1);
+    let xy = [x.x(), x.y()];
+    wk_try!((h.geometry_start)(&meta, part_id, data));
+    wk_try!((h.coord)(&meta, xy.as_ptr(), 0, data));
+    (h.geometry_end)(&meta, part_id, data)
+}
+
+unsafe fn read_linestring(x: &[Coord], part_id: u32, h: &WkHandler) -> c_int {
+    let data = h.handler_data;
+    let meta = WkMeta::new(WK_LINESTRING, x.len() as u32);
+    wk_try!((h.geometry_start)(&meta, part_id, data));
+    wk_try!(read_coords(x, &meta, h));
+    (h.geometry_end)(&meta, part_id, data)
+}
+
+unsafe fn read_rings<'a>(
+    rings: impl Iterator<Item = &'a [Coord]>,
+    n_rings: usize,
+    part_id: u32,
+    h: &WkHandler,
+) -> c_int {
+    let data = h.handler_data;
+
+    let meta = WkMeta::new(WK_POLYGON, n_rings as u32);
+    wk_try!((h.geometry_start)(&meta, part_id, data));
+
+    for (i, ring) in rings.take(n_rings).enumerate() {
+        let size = ring.len() as u32;
+        wk_try!((h.ring_start)(&meta, size, i as u32, data));
+        wk_try!(read_coords(ring, &meta, h));
+        wk_try!((h.ring_end)(&meta, size, i as u32, data));
+    }
+
+    (h.geometry_end)(&meta, part_id, data)
+}
+
+unsafe fn read_polygon(x: &Polygon, part_id: u32, h: &WkHandler) -> c_int {
+    // an empty exterior ring is an empty polygon
+    let n_rings = if x.exterior().0.is_empty() {
+        0
+    } else {
+        x.interiors().len() + 1
+    };
+
+    let rings = std::iter::once(x.exterior())
+        .chain(x.interiors().iter())
+        .map(|ring| ring.0.as_slice());
+
+    read_rings(rings, n_rings, part_id, h)
+}
+
+unsafe fn read_geometry(x: &Geometry, part_id: u32, h: &WkHandler) -> c_int {
+    let data = h.handler_data;
+
+    match x {
+        Geometry::Point(p) => read_point(p, part_id, h),
+        Geometry::LineString(l) => read_linestring(&l.0, part_id, h),
+        Geometry::Polygon(p) => read_polygon(p, part_id, h),
+        Geometry::MultiPoint(mp) => {
+            let meta = WkMeta::new(WK_MULTIPOINT, mp.0.len() as u32);
+            wk_try!((h.geometry_start)(&meta, part_id, data));
+            for (i, p) in mp.0.iter().enumerate() {
+                wk_try!(read_point(p, i as u32, h));
+            }
+            (h.geometry_end)(&meta, part_id, data)
+        }
+        Geometry::MultiLineString(ml) => {
+            let meta = WkMeta::new(WK_MULTILINESTRING, ml.0.len() as u32);
+            wk_try!((h.geometry_start)(&meta, part_id, data));
+            for (i, l) in ml.0.iter().enumerate() {
+                wk_try!(read_linestring(&l.0, i as u32, h));
+            }
+            (h.geometry_end)(&meta, part_id, data)
+        }
+        Geometry::MultiPolygon(mp) => {
+            let meta = WkMeta::new(WK_MULTIPOLYGON, mp.0.len() as u32);
+            wk_try!((h.geometry_start)(&meta, part_id, data));
+            for (i, p) in mp.0.iter().enumerate() {
+                wk_try!(read_polygon(p, i as u32, h));
+            }
+            (h.geometry_end)(&meta, part_id, data)
+        }
+        Geometry::GeometryCollection(gc) => {
+            let meta = WkMeta::new(WK_GEOMETRYCOLLECTION, gc.0.len() as u32);
+            wk_try!((h.geometry_start)(&meta, part_id, data));
+            for (i, g) in gc.0.iter().enumerate() {
+                wk_try!(read_geometry(g, i as u32, h));
+            }
+            (h.geometry_end)(&meta, part_id, data)
+        }
+        Geometry::Line(l) => read_linestring(&[l.start, l.end], part_id, h),
+        Geometry::Rect(r) => {
+            let (min, max) = (r.min(), r.max());
+            let ring = [
+                Coord { x: max.x, y: min.y },
+                Coord { x: max.x, y: max.y },
+                Coord { x: min.x, y: max.y },
+                Coord { x: min.x, y: min.y },
+                Coord { x: max.x, y: min.y },
+            ];
+            read_rings(std::iter::once(&ring[..]), 1, part_id, h)
+        }
+        Geometry::Triangle(t) => {
+            let ring = [t.0, t.1, t.2, t.0];
+            read_rings(std::iter::once(&ring[..]), 1, part_id, h)
+        }
+    }
+}
+
+// Writer -----------------------------------------------------------------
+
+// Geometries are built from a stack as they may be nested
+#[derive(Default)]
+struct RsgeoWriter {
+    result: Vec<Option<Geometry>>,
+    stack: Vec<WriterFrame>,
+    feature: Option<Geometry>,
+    // kept so the result stays protected until it is returned
+    output: Option<Robj>,
+    error: Option<CString>,
+}
+
+struct WriterFrame {
+    geometry_type: u32,
+    coords: Vec<Coord>,
+    rings: Vec<LineString>,
+    parts: Vec<Geometry>,
+}
+
+impl WriterFrame {
+    fn into_geometry(self) -> Option<Geometry> {
+        let parts = self.parts.into_iter();
+
+        match self.geometry_type {
+            // an empty point has no coordinates and is missing
+            WK_POINT => self.coords.first().map(|c| Geometry::Point(Point(*c))),
+            WK_LINESTRING => Some(Geometry::LineString(LineString::new(self.coords))),
+            WK_POLYGON => {
+                let mut rings = self.rings.into_iter();
+                let ext = rings.next().unwrap_or(LineString::new(vec![]));
+                Some(Geometry::Polygon(Polygon::new(ext, rings.collect())))
+            }
+            WK_MULTIPOINT => Some(Geometry::MultiPoint(MultiPoint::new(
+                parts.filter_map(|g| Point::try_from(g).ok()).collect(),
+            ))),
+            WK_MULTILINESTRING => Some(Geometry::MultiLineString(MultiLineString::new(
+                parts.filter_map(|g| LineString::try_from(g).ok()).collect(),
+            ))),
+            WK_MULTIPOLYGON => Some(Geometry::MultiPolygon(MultiPolygon::new(
+                parts.filter_map(|g| Polygon::try_from(g).ok()).collect(),
+            ))),
+            _ => Some(Geometry::GeometryCollection(GeometryCollection::new_from(
+                parts.collect(),
+            ))),
+        }
+    }
+}
+
+#[extendr]
+fn rsgeo_writer_() -> Robj {
+    let writer = Box::<RsgeoWriter>::default();
+
+    let handler = Box::new(WkHandler {
+        api_version: 1,
+        dirty: 0,
+        handler_data: Box::into_raw(writer) as *mut c_void,
+        initialize: rsgeo_writer_initialize,
+        vector_start: writer_vector_start,
+        feature_start: writer_feature_start,
+        null_feature: writer_null_feature,
+        geometry_start: writer_geometry_start,
+        ring_start: writer_ring_start,
+        coord: writer_coord,
+        ring_end: writer_ring_end,
+        geometry_end: writer_geometry_end,
+        feature_end: writer_feature_end,
+        vector_end: rsgeo_writer_vector_end,
+        error: writer_error,
+        deinitialize: writer_deinitialize,
+        finalizer: writer_finalizer,
+    });
+
+    unsafe {
+        let xptr = R_MakeExternalPtr(
+            Box::into_raw(handler) as *mut c_void,
+            R_NilValue,
+            R_NilValue,
+        );
+        R_RegisterCFinalizerEx(xptr, Some(handler_xptr_finalize), Rboolean::FALSE);
+        Robj::from_sexp(xptr)
+    }
+}
+
+unsafe fn writer_data<'a>(data: *mut c_void) -> &'a mut RsgeoWriter {
+    &mut *(data as *mut RsgeoWriter)
+}
+
+unsafe extern "C" fn writer_vector_start(meta: *const WkVectorMeta, data: *mut c_void) -> c_int {
+    let w = writer_data(data);
+    w.result.clear();
+    w.stack.clear();
+    if (*meta).size > 0 {
+        w.result.reserve((*meta).size as usize);
+    }
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_feature_start(
+    _meta: *const WkVectorMeta,
+    _feat_id: isize,
+    data: *mut c_void,
+) -> c_int {
+    let w = writer_data(data);
+    w.feature = None;
+    w.stack.clear();
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_null_feature(data: *mut c_void) -> c_int {
+    writer_data(data).feature = None;
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_geometry_start(
+    meta: *const WkMeta,
+    _part_id: u32,
+    data: *mut c_void,
+) -> c_int {
+    let size = match (*meta).size {
+        WK_SIZE_UNKNOWN => 0,
+        n => n as usize,
+    };
+
+    writer_data(data).stack.push(WriterFrame {
+        geometry_type: (*meta).geometry_type,
+        coords: Vec::with_capacity(size),
+        rings: Vec::new(),
+        parts: Vec::new(),
+    });
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_ring_start(
+    _meta: *const WkMeta,
+    size: u32,
+    _ring_id: u32,
+    data: *mut c_void,
+) -> c_int {
+    if let Some(frame) = writer_data(data).stack.last_mut() {
+        frame.coords.clear();
+        if size != WK_SIZE_UNKNOWN {
+            frame.coords.reserve(size as usize);
+        }
+    }
+    WK_CONTINUE
+}
+
+// only x and y are kept, z and m values are dropped
+unsafe extern "C" fn writer_coord(
+    _meta: *const WkMeta,
+    coord: *const f64,
+    _coord_id: u32,
+    data: *mut c_void,
+) -> c_int {
+    if let Some(frame) = writer_data(data).stack.last_mut() {
+        frame.coords.push(Coord {
+            x: *coord,
+            y: *coord.add(1),
+        });
+    }
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_ring_end(
+    _meta: *const WkMeta,
+    _size: u32,
+    _ring_id: u32,
+    data: *mut c_void,
+) -> c_int {
+    if let Some(frame) = writer_data(data).stack.last_mut() {
+        let ring = std::mem::take(&mut frame.coords);
+        frame.rings.push(LineString::new(ring));
+    }
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_geometry_end(
+    _meta: *const WkMeta,
+    _part_id: u32,
+    data: *mut c_void,
+) -> c_int {
+    let w = writer_data(data);
+
+    if let Some(frame) = w.stack.pop() {
+        let geom = frame.into_geometry();
+        match w.stack.last_mut() {
+            Some(parent) => parent.parts.extend(geom),
+            None => w.feature = geom,
+        }
+    }
+    WK_CONTINUE
+}
+
+unsafe extern "C" fn writer_feature_end(
+    _meta: *const WkVectorMeta,
+    _feat_id: isize,
+    data: *mut c_void,
+) -> c_int {
+    let w = writer_data(data);
+    let feature = w.feature.take();
+    w.result.push(feature);
+    WK_CONTINUE
+}
+
+// Called by `rsgeo_writer_vector_end()` in src/wk.c
+#[no_mangle]
+unsafe extern "C" fn rsgeo_writer_result(_meta: *const WkVectorMeta, data: *mut c_void) -> SEXP {
+    let w = writer_data(data);
+    let res = geoms_to_rsgeo(std::mem::take(&mut w.result));
+    w.output.insert(res).get()
+}
+
+// Called by `rsgeo_writer_vector_end()` in src/wk.c
+#[no_mangle]
+unsafe extern "C" fn rsgeo_writer_error(data: *mut c_void) -> *const c_char {
+    match &writer_data(data).error {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+// the first error aborts reading and is raised by vector_end
+unsafe extern "C" fn writer_error(message: *const c_char, data: *mut c_void) -> c_int {
+    let w = writer_data(data);
+    if w.error.is_none() {
+        w.error = Some(CStr::from_ptr(message).to_owned());
+    }
+    WK_ABORT
+}
+
+unsafe extern "C" fn writer_deinitialize(data: *mut c_void) {
+    writer_data(data).stack.clear();
+}
+
+unsafe extern "C" fn writer_finalizer(data: *mut c_void) {
+    if !data.is_null() {
+        drop(Box::from_raw(data as *mut RsgeoWriter));
+    }
+}
+
+unsafe extern "C" fn handler_xptr_finalize(xptr: SEXP) {
+    let handler = R_ExternalPtrAddr(xptr) as *mut WkHandler;
+    if !handler.is_null() {
+        ((*handler).finalizer)((*handler).handler_data);
+        drop(Box::from_raw(handler));
+        R_ClearExternalPtr(xptr);
+    }
+}
+
+extendr_module! {
+    mod wkimpl;
+    fn rsgeo_writer_;
+}
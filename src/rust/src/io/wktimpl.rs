@@ -1,54 +1,76 @@
-use geo_types::Geometry;
-use wkt::ToWkt;
-use wkt::TryFromWkt;
-//use std::io::Read;
 use extendr_api::prelude::*;
-use extendr_api::{extendr, Robj, Strings};
+use geo_types::Geometry;
+use sfconversions::{vctrs::verify_rsgeo, Geom};
+use wkt::geo_types_from_wkt::Error as WktError;
+use wkt::{ToWkt, TryFromWkt};
 
-use crate::to_pntr;
-use crate::types::Geom;
+use crate::utils::geoms_to_rsgeo;
 
 #[extendr]
-/// WKT translation
+/// Well-Known Text
+///
+/// Parse Well-Known Text (WKT) into an `rsgeo` vector or serialize
+/// an `rsgeo` vector to WKT.
+///
+/// Missing values are preserved in both directions. Empty points such as
+/// `"POINT EMPTY"` cannot be represented and are returned as missing.
+///
+/// @param x for `wkt_to_geoms()` a character vector of WKT geometries.
+///   For `wkt_from_geoms()` an object of class `rsgeo`.
 ///
 /// @export
 /// @rdname wkt
-pub fn wkt_to_geom(x: &str) -> Robj {
-    let x = Geometry::try_from_wkt_str(x).unwrap();
-    to_pntr(Geom::from(x))
-}
+/// @examples
+/// x <- wkt_to_geoms(c("POINT (0 1)", NA, "POINT (10 5)"))
+/// x
+/// wkt_from_geoms(x)
+/// @returns
+/// - `wkt_to_geoms()` returns an object of class `rsgeo`. If all geometries are
+/// the same type the vector will have the corresponding `rs_*` class.
+/// - `wkt_from_geoms()` returns a character vector
+fn wkt_to_geoms(x: Strings) -> Robj {
+    let res_vec = x
+        .iter()
+        .enumerate()
+        .map(|(i, xi)| {
+            if xi.is_na() {
+                None
+            } else {
+                match Geometry::try_from_wkt_str(xi.as_str()) {
+                    Ok(g) => Some(g),
+                    Err(WktError::PointConversionError) => None,
+                    Err(e) => panic!("failed to parse WKT at element {}: {e}", i + 1),
+                }
+            }
+        })
+        .collect::<Vec<Option<Geometry>>>();
 
-#[extendr]
-/// @rdname wkt
-/// @export
-pub fn wkt_to_geoms(x: Strings) -> Robj {
-    let res = x.iter().map(|x| wkt_to_geom(x.as_str())).collect::<List>();
-
-    crate::utils::restore_geoms(res.into())
-}
-#[extendr]
-/// @rdname wkt
-/// @export
-pub fn wkt_from_geom(x: Robj) -> Strings {
-    let res = Geom::from(x).geom.to_wkt().to_string();
-    Strings::from(res)
+    geoms_to_rsgeo(res_vec)
 }
 
 #[extendr]
 /// @rdname wkt
 /// @export
-pub fn wkt_from_geoms(x: List) -> Strings {
-    let all_wkt = x
-        .into_iter()
-        .map(|(_, robj)| Geom::from(robj).geom.to_wkt().to_string());
+fn wkt_from_geoms(x: List) -> Strings {
+    verify_rsgeo(&x);
+
+    let res_vec = x
+        .iter()
+        .map(|(_, xi)| {
+            if xi.is_null() {
+                Rstr::na()
+            } else {
+                let g = <&Geom>::try_from(&xi).unwrap();
+                Rstr::from(g.geom.wkt_string())
+            }
+        })
+        .collect::<Vec<Rstr>>();
 
-    Strings::from_iter(all_wkt)
+    Strings::from_values(res_vec)
 }
 
 extendr_module! {
     mod wktimpl;
-    fn wkt_to_geom;
-    fn wkt_from_geom;
     fn wkt_to_geoms;
     fn wkt_from_geoms;
 }
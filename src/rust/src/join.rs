@@ -0,0 +1,340 @@
+use extendr_api::prelude::*;
+use geo::Intersects;
+use geo_types::{Coord, Geometry, Line};
+use rayon::prelude::*;
+use rstar::primitives::{CachedEnvelope, GeomWithData, Line as Edge};
+use rstar::{RTree, RTreeObject, AABB};
+use sfconversions::{geometry_from_list, Geom};
+use std::sync::OnceLock;
+
+use crate::spatial_index::{create_cached_rtree, MEAN_EARTH_RADIUS};
+
+// Geometries with at least this many edges are prepared the first time they
+// are a candidate
+const PREPARE_MIN_EDGES: usize = 64;
+
+// When both inputs have at least this many geometries the candidates are
+// found by traversing an R-tree of each at the same time
+const DUAL_TREE_MIN_SIZE: usize = 10_000;
+
+// all of the edges of a geometry
+fn edges(x: &Geometry) -> Vec<Line> {
+    match x {
+        Geometry::Point(_) | Geometry::MultiPoint(_) => vec![],
+        Geometry::Line(l) => vec![*l],
+        Geometry::LineString(l) => l.lines().collect(),
+        Geometry::MultiLineString(ml) => ml.0.iter().flat_map(|l| l.lines()).collect(),
+        Geometry::Polygon(p) => std::iter::once(p.exterior())
+            .chain(p.interiors())
+            .flat_map(|r| r.lines())
+            .collect(),
+        Geometry::MultiPolygon(mp) => {
+            mp.0.iter()
+                .flat_map(|p| std::iter::once(p.exterior()).chain(p.interiors()))
+                .flat_map(|r| r.lines())
+                .collect()
+        }
+        Geometry::Rect(r) => edges(&Geometry::Polygon(r.to_polygon())),
+        Geometry::Triangle(t) => edges(&Geometry::Polygon(t.to_polygon())),
+        Geometry::GeometryCollection(gc) => gc.0.iter().flat_map(edges).collect(),
+    }
+}
+
+// the first coordinate of each point, linestring, and polygon exterior. If
+// the edges of two geometries do not intersect each of these parts is either
+// entirely inside or entirely outside of the other geometry.
+fn part_coords(x: &Geometry) -> Vec<Coord> {
+    match x {
+        Geometry::Point(p) => vec![p.0],
+        Geometry::MultiPoint(mp) => mp.0.iter().map(|p| p.0).collect(),
+        Geometry::Line(l) => vec![l.start],
+        Geometry::LineString(l) => l.0.first().copied().into_iter().collect(),
+        Geometry::MultiLineString(ml) => ml.0.iter().filter_map(|l| l.0.first().copied()).collect(),
+        Geometry::Polygon(p) => p.exterior().0.first().copied().into_iter().collect(),
+        Geometry::MultiPolygon(mp) => {
+            mp.0.iter()
+                .filter_map(|p| p.exterior().0.first().copied())
+                .collect()
+        }
+        Geometry::Rect(r) => vec![r.min()],
+        Geometry::Triangle(t) => vec![t.0],
+        Geometry::GeometryCollection(gc) => gc.0.iter().flat_map(part_coords).collect(),
+    }
+}
+
+fn edge_line(x: &Edge<[f64; 2]>) -> Line {
+    Line::new(
+        Coord {
+            x: x.from[0],
+            y: x.from[1],
+        },
+        Coord {
+            x: x.to[0],
+            y: x.to[1],
+        },
+    )
+}
+
+/// A geometry with an R-tree of its edges. Intersection tests against a
+/// prepared geometry only visit the edges near the other geometry rather than
+/// every edge which makes repeated tests against large polygons and lines
+/// much cheaper.
+pub struct PreparedGeom {
+    edges: RTree<Edge<[f64; 2]>>,
+    // polygons can contain a geometry without any of their edges intersecting
+    polygonal: bool,
+    // a coordinate of each part used to test if it is inside another geometry
+    coords: Vec<Coord>,
+}
+
+impl PreparedGeom {
+    /// Prepare a geometry. Only linear and polygonal geometries with many
+    /// edges are prepared.
+    pub fn new(x: &Geometry) -> Option<Self> {
+        let polygonal = match x {
+            Geometry::Polygon(_) | Geometry::MultiPolygon(_) => true,
+            Geometry::LineString(_) | Geometry::MultiLineString(_) => false,
+            _ => return None,
+        };
+
+        let lines = edges(x);
+        if lines.len() < PREPARE_MIN_EDGES {
+            return None;
+        }
+
+        let edges = lines
+            .into_iter()
+            .map(|l| Edge::new([l.start.x, l.start.y], [l.end.x, l.end.y]))
+            .collect::<Vec<Edge<[f64; 2]>>>();
+
+        Some(Self {
+            edges: RTree::bulk_load(edges),
+            polygonal,
+            coords: part_coords(x),
+        })
+    }
+
+    fn on_edge(&self, x: &Coord) -> bool {
+        self.edges
+            .locate_in_envelope_intersecting(&AABB::from_point([x.x, x.y]))
+            .any(|e| edge_line(e).intersects(x))
+    }
+
+    // even-odd ray casting using only the edges the ray could cross
+    fn contains_coord(&self, x: &Coord) -> bool {
+        if !self.polygonal {
+            return false;
+        }
+
+        let max_x = self.edges.root().envelope().upper()[0];
+        if x.x > max_x {
+            return false;
+        }
+
+        let ray = AABB::from_corners([x.x, x.y], [max_x, x.y]);
+
+        self.edges
+            .locate_in_envelope_intersecting(&ray)
+            .filter(|e| {
+                let (a, b) = (e.from, e.to);
+                (a[1] > x.y) != (b[1] > x.y)
+                    && x.x < (b[0] - a[0]) * (x.y - a[1]) / (b[1] - a[1]) + a[0]
+            })
+            .count()
+            % 2
+            == 1
+    }
+
+    /// Does the prepared geometry intersect `x`?
+    pub fn intersects(&self, x: &Geometry) -> bool {
+        let coords = match x {
+            Geometry::Point(p) => vec![p.0],
+            Geometry::MultiPoint(mp) => mp.0.iter().map(|p| p.0).collect(),
+            Geometry::GeometryCollection(gc) => return gc.0.iter().any(|g| self.intersects(g)),
+            _ => vec![],
+        };
+
+        if !coords.is_empty() {
+            return coords
+                .iter()
+                .any(|c| self.on_edge(c) || self.contains_coord(c));
+        }
+
+        let lines = edges(x);
+
+        // the boundaries cross or touch
+        let crosses = lines.iter().any(|l| {
+            let env = AABB::from_corners([l.start.x, l.start.y], [l.end.x, l.end.y]);
+            self.edges
+                .locate_in_envelope_intersecting(&env)
+                .any(|e| edge_line(e).intersects(l))
+        });
+
+        if crosses {
+            return true;
+        }
+
+        // otherwise a part of one geometry must be entirely inside of the other
+        part_coords(x).iter().any(|c| self.contains_coord(c))
+            || self.coords.iter().any(|c| x.intersects(c))
+    }
+}
+
+/// How the envelope of each geometry in `y` is expanded to search for
+/// candidates in `x`
+#[derive(Clone, Copy, Debug)]
+pub enum SearchEnvelope {
+    Intersecting,
+    /// Expanded by a distance in the units of the coordinates
+    Planar(f64),
+    /// Expanded by a distance in meters for longitude and latitude envelopes
+    Haversine(f64),
+}
+
+impl SearchEnvelope {
+    pub fn expand(&self, x: &AABB<[f64; 2]>) -> AABB<[f64; 2]> {
+        let (lower, upper) = (x.lower(), x.upper());
+
+        match self {
+            SearchEnvelope::Intersecting => *x,
+            SearchEnvelope::Planar(d) => {
+                AABB::from_corners([lower[0] - d, lower[1] - d], [upper[0] + d, upper[1] + d])
+            }
+            SearchEnvelope::Haversine(d) => {
+                // a distance along a meridian is the same everywhere
+                let angle = d / MEAN_EARTH_RADIUS;
+                let dlat = angle.to_degrees();
+                let min_lat = (lower[1] - dlat).max(-90.0);
+                let max_lat = (upper[1] + dlat).min(90.0);
+
+                // meridians converge towards the poles so the longitude range
+                // is widened using the latitude closest to a pole. Near a
+                // pole or across the antimeridian every longitude is searched
+                let max_abs_lat = lower[1].abs().max(upper[1].abs()).to_radians();
+                let ratio = angle.sin() / max_abs_lat.cos();
+                let dlon = if min_lat > -90.0 && max_lat < 90.0 && ratio < 1.0 {
+                    ratio.asin().to_degrees()
+                } else {
+                    f64::INFINITY
+                };
+
+                let (min_lon, max_lon) = (lower[0] - dlon, upper[0] + dlon);
+                let (min_lon, max_lon) = if min_lon < -180.0 || max_lon > 180.0 {
+                    (-180.0, 180.0)
+                } else {
+                    (min_lon, max_lon)
+                };
+
+                AABB::from_corners([min_lon, min_lat], [max_lon, max_lat])
+            }
+        }
+    }
+}
+
+type Leaf = GeomWithData<CachedEnvelope<Geom>, usize>;
+
+/// A geometry from `x` that is a candidate for a join. Its prepared geometry
+/// is created once and shared by every geometry in `y` that it is tested
+/// against.
+pub struct Candidate<'a> {
+    pub geom: &'a Geometry,
+    prepared: &'a OnceLock<Option<PreparedGeom>>,
+}
+
+impl<'a> Candidate<'a> {
+    fn new(leaf: &'a Leaf, prepared: &'a [OnceLock<Option<PreparedGeom>>]) -> Self {
+        Self {
+            geom: &leaf.geom().geom,
+            prepared: &prepared[leaf.data],
+        }
+    }
+
+    pub fn prepared(&self) -> Option<&PreparedGeom> {
+        self.prepared
+            .get_or_init(|| PreparedGeom::new(self.geom))
+            .as_ref()
+    }
+
+    pub fn intersects(&self, y: &Geometry) -> bool {
+        match self.prepared() {
+            Some(p) => p.intersects(y),
+            None => self.geom.intersects(y),
+        }
+    }
+}
+
+fn merge(mut a: Vec<(usize, usize)>, mut b: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    a.append(&mut b);
+    a
+}
+
+/// Tests `f(x, y)` for every pair of geometries whose envelopes intersect
+/// after the envelope of `y` is expanded by `search`. Returns the sorted
+/// 1-based positions of the matching geometries in `y` for each geometry in
+/// `x`.
+///
+/// Each thread collects its matches into its own buffer which are merged
+/// once every pair has been tested. If both `x` and `y` are large and the
+/// envelopes are not expanded the candidates are found with a simultaneous
+/// traversal of an R-tree of each input. Otherwise, the R-tree of `x` is
+/// queried with each geometry in `y`.
+pub fn sparse_join<F>(x: List, y: List, search: SearchEnvelope, f: F) -> Vec<Vec<i32>>
+where
+    F: Fn(&Candidate, &Geometry) -> bool + Sync,
+{
+    let n = x.len();
+    let m = y.len();
+
+    let xtree = create_cached_rtree(x);
+    let prepared = (0..n)
+        .map(|_| OnceLock::new())
+        .collect::<Vec<OnceLock<Option<PreparedGeom>>>>();
+
+    let dual_tree =
+        matches!(search, SearchEnvelope::Intersecting) && n.min(m) >= DUAL_TREE_MIN_SIZE;
+
+    let hits = if dual_tree {
+        let ytree = create_cached_rtree(y);
+
+        // candidates are handed to the thread pool as they are found rather
+        // than stored as every pair may not fit in memory
+        xtree
+            .intersection_candidates_with_other_tree(&ytree)
+            .par_bridge()
+            .fold(Vec::new, |mut acc, (xl, yl)| {
+                if f(&Candidate::new(xl, &prepared), &yl.geom().geom) {
+                    acc.push((xl.data, yl.data));
+                }
+                acc
+            })
+            .reduce(Vec::new, merge)
+    } else {
+        geometry_from_list(y)
+            .into_par_iter()
+            .enumerate()
+            .fold(Vec::new, |mut acc, (j, yj)| {
+                if let Some(yj) = yj {
+                    let yj = Geom::from(yj);
+                    let env = search.expand(&yj.envelope());
+
+                    for xl in xtree.locate_in_envelope_intersecting(&env) {
+                        if f(&Candidate::new(xl, &prepared), &yj.geom) {
+                            acc.push((xl.data, j));
+                        }
+                    }
+                }
+                acc
+            })
+            .reduce(Vec::new, merge)
+    };
+
+    let mut index = vec![Vec::new(); n];
+    for (i, j) in hits {
+        index[i].push((j as i32) + 1);
+    }
+
+    // sorted so that results are reproducible regardless of thread order
+    index.par_iter_mut().for_each(|xi| xi.sort_unstable());
+
+    index
+}
@@ -2,15 +2,19 @@
 mod area;
 mod boundary;
 mod casting;
+mod contiguity;
 mod coord_utils;
 mod densify;
 mod distance;
+mod grids;
+mod join;
 mod length;
+mod overlay;
 mod query;
 mod segmentize;
 mod similarity;
 mod simplification;
-// mod io;
+mod io;
 mod construction;
 mod coords;
 mod spatial_index;
@@ -301,4 +305,9 @@ extendr_module! {
     use union;
     use utils;
     use casting;
+    use io;
+    use grids;
+    use spatial_index;
+    use contiguity;
+    use overlay;
 }
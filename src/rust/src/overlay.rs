@@ -0,0 +1,252 @@
+use extendr_api::prelude::*;
+use geo::dimensions::HasDimensions;
+use geo::{BooleanOps, OpType};
+use geo_types::{Geometry, LineString, MultiLineString, MultiPolygon};
+use rayon::prelude::*;
+use sfconversions::vctrs::as_rsgeo_vctr;
+use sfconversions::{geometry_from_list, Geom};
+
+use crate::join::{sparse_join, SearchEnvelope};
+
+fn as_multipolygon(x: &Geometry) -> Option<MultiPolygon> {
+    match x {
+        Geometry::Polygon(p) => Some(MultiPolygon::new(vec![p.clone()])),
+        Geometry::MultiPolygon(mp) => Some(mp.clone()),
+        Geometry::Rect(r) => Some(MultiPolygon::new(vec![r.to_polygon()])),
+        Geometry::Triangle(t) => Some(MultiPolygon::new(vec![t.to_polygon()])),
+        _ => None,
+    }
+}
+
+fn as_multilinestring(x: &Geometry) -> Option<MultiLineString> {
+    match x {
+        Geometry::Line(l) => Some(MultiLineString::new(vec![LineString::from(*l)])),
+        Geometry::LineString(l) => Some(MultiLineString::new(vec![l.clone()])),
+        Geometry::MultiLineString(ml) => Some(ml.clone()),
+        _ => None,
+    }
+}
+
+/// Apply a boolean operation to two geometries. Polygonal geometries support
+/// every operation. Linear geometries are clipped by polygonal geometries for
+/// intersections and differences. Returns `None` if the result is empty or
+/// the operation is not supported for the geometry types.
+pub fn boolean_op(x: &Geometry, y: &Geometry, op: OpType) -> Option<Geometry> {
+    let res: Geometry = match (as_multipolygon(x), as_multipolygon(y), op) {
+        (Some(xp), Some(yp), _) => xp.boolean_op(&yp, op).into(),
+        (None, Some(yp), OpType::Intersection) => yp.clip(&as_multilinestring(x)?, false).into(),
+        (None, Some(yp), OpType::Difference) => yp.clip(&as_multilinestring(x)?, true).into(),
+        (Some(xp), None, OpType::Intersection) => xp.clip(&as_multilinestring(y)?, false).into(),
+        _ => return None,
+    };
+
+    if res.is_empty() {
+        None
+    } else {
+        Some(res)
+    }
+}
+
+// The class of the result of a boolean operation. Polygons return
+// multipolygons and linestrings clipped by polygons return multilinestrings.
+fn boolean_op_class(x: &List, y: &List, op: OpType) -> &'static str {
+    let polygonal = |x: &List| x.inherits("rs_POLYGON") || x.inherits("rs_MULTIPOLYGON");
+    let linear = |x: &List| x.inherits("rs_LINESTRING") || x.inherits("rs_MULTILINESTRING");
+
+    match op {
+        _ if polygonal(x) && polygonal(y) => "multipolygon",
+        OpType::Intersection if (linear(x) && polygonal(y)) || (polygonal(x) && linear(y)) => {
+            "multilinestring"
+        }
+        OpType::Difference if linear(x) && polygonal(y) => "multilinestring",
+        OpType::Intersection => {
+            panic!("`x` and `y` must be polygons, or linestrings and polygons")
+        }
+        OpType::Difference => {
+            panic!("`x` must be polygons or linestrings and `y` must be polygons")
+        }
+        _ => panic!("`x` and `y` must be of class `rs_POLYGON` or `rs_MULTIPOLYGON`"),
+    }
+}
+
+fn geoms_to_rsgeo_class(x: Vec<Option<Geometry>>, cls: &str) -> Robj {
+    let res_vec = x
+        .into_iter()
+        .map(|xi| match xi {
+            Some(g) => Geom::from(g).into_robj(),
+            None => NULL.into_robj(),
+        })
+        .collect::<Vec<Robj>>();
+
+    as_rsgeo_vctr(List::from_values(res_vec), cls)
+}
+
+fn boolean_op_pairwise(x: List, y: List, op: OpType) -> Robj {
+    let cls = boolean_op_class(&x, &y, op);
+
+    if x.len() != y.len() {
+        panic!("`x` and `y` must be the same length")
+    }
+
+    let x = geometry_from_list(x);
+    let y = geometry_from_list(y);
+
+    let res = x
+        .into_par_iter()
+        .zip(y.into_par_iter())
+        .map(|(xi, yi)| match (xi, yi) {
+            (Some(xi), Some(yi)) => boolean_op(&xi, &yi, op),
+            _ => None,
+        })
+        .collect::<Vec<Option<Geometry>>>();
+
+    geoms_to_rsgeo_class(res, cls)
+}
+
+#[extendr]
+/// Boolean Operations
+///
+/// Compute the intersection, difference, symmetric difference, or union of
+/// each pair of geometries in two vectors.
+///
+/// @details
+///
+/// Every operation is supported between polygonal geometries. Linestrings
+/// can also be clipped by polygons: `intersection_pairwise()` accepts
+/// linestrings as either `x` or `y` and `difference_pairwise()` accepts
+/// linestrings as `x`.
+///
+/// Operations between polygons return an `rs_MULTIPOLYGON` and clipped
+/// linestrings return an `rs_MULTILINESTRING`. Empty results are missing.
+///
+/// @param x an object of class `rsgeo`
+/// @param y an object of class `rsgeo` the same length as `x`
+///
+/// @export
+/// @rdname boolean_ops
+/// @returns an object of class `rs_MULTIPOLYGON` or `rs_MULTILINESTRING` the
+/// same length as `x`
+/// @examples
+/// x <- geom_polygon(c(0, 2, 2, 0, 0), c(0, 0, 2, 2, 0))
+/// y <- geom_polygon(c(1, 3, 3, 1, 1), c(1, 1, 3, 3, 1))
+/// intersection_pairwise(x, y)
+/// difference_pairwise(x, y)
+/// sym_difference_pairwise(x, y)
+/// union_pairwise(x, y)
+///
+/// lns <- geom_linestring(c(-1, 3), c(1, 1))
+/// intersection_pairwise(lns, x)
+/// difference_pairwise(lns, x)
+fn intersection_pairwise(x: List, y: List) -> Robj {
+    boolean_op_pairwise(x, y, OpType::Intersection)
+}
+
+#[extendr]
+/// @export
+/// @rdname boolean_ops
+fn difference_pairwise(x: List, y: List) -> Robj {
+    boolean_op_pairwise(x, y, OpType::Difference)
+}
+
+#[extendr]
+/// @export
+/// @rdname boolean_ops
+fn sym_difference_pairwise(x: List, y: List) -> Robj {
+    boolean_op_pairwise(x, y, OpType::Xor)
+}
+
+#[extendr]
+/// @export
+/// @rdname boolean_ops
+fn union_pairwise(x: List, y: List) -> Robj {
+    boolean_op_pairwise(x, y, OpType::Union)
+}
+
+#[extendr]
+/// Intersection Overlay
+///
+/// Intersect every geometry in `x` with every geometry in `y`. This is the
+/// equivalent of `sf::st_intersection()` for two layers.
+///
+/// @details
+///
+/// The pairs of geometries that intersect are found with an R-tree and only
+/// the non-empty intersections are returned. `x` and `y` must be polygonal or
+/// one of them may be linestrings which are clipped by the polygons of the
+/// other.
+///
+/// @param x an object of class `rsgeo`
+/// @param y an object of class `rsgeo`
+///
+/// @export
+/// @returns
+/// A list with three elements of the same length:
+///
+/// - `x` the position of the intersected geometry in `x`
+/// - `y` the position of the intersected geometry in `y`
+/// - `geometry` an object of class `rs_MULTIPOLYGON` or `rs_MULTILINESTRING`
+///   containing the intersections
+/// @examples
+/// x <- geom_polygon(
+///   c(0, 2, 2, 0, 0, 2, 4, 4, 2, 2),
+///   c(0, 0, 2, 2, 0, 0, 0, 2, 2, 0),
+///   rep(1:2, each = 5)
+/// )
+/// y <- geom_polygon(c(1, 3, 3, 1, 1), c(1, 1, 3, 3, 1))
+/// intersection_overlay(x, y)
+///
+/// lns <- geom_linestring(c(-1, 5), c(1, 1))
+/// intersection_overlay(lns, x)
+fn intersection_overlay(x: List, y: List) -> List {
+    let cls = boolean_op_class(&x, &y, OpType::Intersection);
+
+    let xg = geometry_from_list(x.clone());
+    let yg = geometry_from_list(y.clone());
+
+    let index = sparse_join(x, y, SearchEnvelope::Intersecting, |xi, yi| {
+        xi.intersects(yi)
+    });
+
+    let pairs = index
+        .into_iter()
+        .enumerate()
+        .flat_map(|(i, js)| js.into_iter().map(move |j| (i, (j - 1) as usize)))
+        .collect::<Vec<(usize, usize)>>();
+
+    // geometries that only touch have an empty intersection and are dropped
+    let res = pairs
+        .into_par_iter()
+        .filter_map(|(i, j)| {
+            let geom = boolean_op(xg[i].as_ref()?, yg[j].as_ref()?, OpType::Intersection)?;
+            Some((i, j, geom))
+        })
+        .collect::<Vec<(usize, usize, Geometry)>>();
+
+    let xi = res
+        .iter()
+        .map(|(i, _, _)| (*i as i32) + 1)
+        .collect::<Vec<i32>>();
+    let yi = res
+        .iter()
+        .map(|(_, j, _)| (*j as i32) + 1)
+        .collect::<Vec<i32>>();
+    let geoms = res
+        .into_iter()
+        .map(|(_, _, g)| Some(g))
+        .collect::<Vec<Option<Geometry>>>();
+
+    list!(
+        x = Integers::from_values(xi),
+        y = Integers::from_values(yi),
+        geometry = geoms_to_rsgeo_class(geoms, cls)
+    )
+}
+
+extendr_module! {
+    mod overlay;
+    fn intersection_pairwise;
+    fn difference_pairwise;
+    fn sym_difference_pairwise;
+    fn union_pairwise;
+    fn intersection_overlay;
+}
@@ -6,6 +6,18 @@ use sfconversions::{
     Geom,
 };
 
+/// Ramer–Douglas–Peucker simplification of linear and polygonal geometries.
+/// Other geometry types return `None`.
+pub fn simplify_geometry(x: Geometry, epsilon: f64) -> Option<Geometry> {
+    match x {
+        Geometry::LineString(geom) => Some(geom.simplify(&epsilon).into()),
+        Geometry::MultiLineString(geom) => Some(geom.simplify(&epsilon).into()),
+        Geometry::Polygon(geom) => Some(geom.simplify(&epsilon).into()),
+        Geometry::MultiPolygon(geom) => Some(geom.simplify(&epsilon).into()),
+        _ => None,
+    }
+}
+
 #[extendr]
 fn simplify_geoms_(x: List, epsilon: Doubles) -> Robj {
     let n_e = epsilon.len();
@@ -33,12 +45,9 @@ fn simplify_geoms_(x: List, epsilon: Doubles) -> Robj {
                 let geom = Geom::try_from(xi).unwrap().geom;
                 let ei = ei.inner();
 
-                match geom {
-                    Geometry::LineString(geom) => Geom::from(geom.simplify(&ei)).into(),
-                    Geometry::MultiLineString(geom) => Geom::from(geom.simplify(&ei)).into(),
-                    Geometry::Polygon(geom) => Geom::from(geom.simplify(&ei)).into(),
-                    Geometry::MultiPolygon(geom) => Geom::from(geom.simplify(&ei)).into(),
-                    _ => NULL.into_robj(),
+                match simplify_geometry(geom, ei) {
+                    Some(geom) => Geom::from(geom).into(),
+                    None => NULL.into_robj(),
                 }
             }
         })
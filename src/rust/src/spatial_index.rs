@@ -1,12 +1,19 @@
 use extendr_api::prelude::*;
 
-// use geo_types::Point;
+use geo::{EuclideanDistance, HaversineDistance, Intersects};
+use geo_types::Geometry;
+use libR_sys::R_ExternalPtrAddr;
+use rayon::prelude::*;
 use rstar::primitives::{CachedEnvelope, GeomWithData};
-use rstar::RTree;
-use sfconversions::{Geom, IntoGeom};
+use rstar::{RTree, RTreeNode, RTreeObject, AABB};
+use sfconversions::{geometry_from_list, Geom, IntoGeom};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+pub type GeomTree = RTree<GeomWithData<CachedEnvelope<Geom>, usize>>;
 
 // use cached envelopes
-pub fn create_cached_rtree(geoms: List) -> RTree<GeomWithData<CachedEnvelope<Geom>, usize>> {
+pub fn create_cached_rtree(geoms: List) -> GeomTree {
     let all_geoms = geoms
         .iter()
         .enumerate()
@@ -24,167 +31,402 @@ pub fn create_cached_rtree(geoms: List) -> RTree<GeomWithData<CachedEnvelope<Geo
     RTree::bulk_load(all_geoms.to_vec())
 }
 
+// minimum distance between two envelopes
+fn envelope_distance(a: &AABB<[f64; 2]>, b: &AABB<[f64; 2]>) -> f64 {
+    let dx = (a.lower()[0] - b.upper()[0])
+        .max(b.lower()[0] - a.upper()[0])
+        .max(0.0);
+    let dy = (a.lower()[1] - b.upper()[1])
+        .max(b.lower()[1] - a.upper()[1])
+        .max(0.0);
+    dx.hypot(dy)
+}
+
+// mean radius of the earth in meters used by geo's haversine distance
+pub(crate) const MEAN_EARTH_RADIUS: f64 = 6371008.8;
+
+// Minimum haversine distance between two lon/lat envelopes. The latitude gap
+// and the smallest longitude gap, including across the antimeridian, are
+// combined with the smallest cosine of latitude in each envelope which gives
+// a lower bound of the haversine of the central angle.
+fn haversine_envelope_distance(a: &AABB<[f64; 2]>, b: &AABB<[f64; 2]>) -> f64 {
+    let hav = |x: f64| (x.to_radians() / 2.0).sin().powi(2);
+
+    let dlat = (a.lower()[1] - b.upper()[1])
+        .max(b.lower()[1] - a.upper()[1])
+        .max(0.0);
+
+    let dlon_gap = (a.lower()[0] - b.upper()[0])
+        .max(b.lower()[0] - a.upper()[0])
+        .max(0.0);
+    let dlon_max = (a.upper()[0] - b.lower()[0]).max(b.upper()[0] - a.lower()[0]);
+    let dlon = dlon_gap.min((360.0 - dlon_max).max(0.0));
+
+    let max_abs_lat = |x: &AABB<[f64; 2]>| x.lower()[1].abs().max(x.upper()[1].abs()).min(90.0);
+    let cos_lat = max_abs_lat(a).to_radians().cos() * max_abs_lat(b).to_radians().cos();
+
+    let h = (hav(dlat) + cos_lat.max(0.0) * hav(dlon)).min(1.0);
+    2.0 * MEAN_EARTH_RADIUS * h.sqrt().asin()
+}
+
+/// The distance metric used to search for nearest neighbors
+#[derive(Clone, Copy, Debug)]
+pub enum Metric {
+    Euclidean,
+    /// Great circle distance in meters between longitude and latitude points
+    Haversine,
+}
+
+impl Metric {
+    // a lower bound of the distance between anything in two envelopes
+    fn lower_bound(&self, a: &AABB<[f64; 2]>, b: &AABB<[f64; 2]>) -> f64 {
+        match self {
+            Metric::Euclidean => envelope_distance(a, b),
+            Metric::Haversine => haversine_envelope_distance(a, b),
+        }
+    }
+
+    pub fn distance(&self, a: &Geometry, b: &Geometry) -> f64 {
+        match self {
+            Metric::Euclidean => a.euclidean_distance(b),
+            Metric::Haversine => match (a, b) {
+                (Geometry::Point(a), Geometry::Point(b)) => a.haversine_distance(b),
+                _ => panic!("haversine distance is only supported between points"),
+            },
+        }
+    }
+}
+
+// An entry in the best-first queue. Nodes are keyed by the distance to
+// their envelope which is a lower bound of the distance to anything they
+// contain. Leaves are keyed by their exact distance.
+enum Candidate<'a> {
+    Node(&'a RTreeNode<GeomWithData<CachedEnvelope<Geom>, usize>>),
+    Leaf(usize),
+}
+
+struct QueueItem<'a> {
+    dist: f64,
+    item: Candidate<'a>,
+}
+
+impl PartialEq for QueueItem<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueItem<'_> {}
+
+impl PartialOrd for QueueItem<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// reversed so that the BinaryHeap pops the closest item first
+impl Ord for QueueItem<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .dist
+            .partial_cmp(&self.dist)
+            .unwrap_or(Ordering::Equal)
+    }
+}
+
+/// Find the `k` geometries in `tree` nearest to `x` using a best-first
+/// traversal of the tree's nodes. Only geometries within `max_distance` are
+/// returned. Results are the position of the geometry and its distance
+/// ordered from nearest to farthest.
+pub fn nearest_k(
+    tree: &GeomTree,
+    x: &Geom,
+    k: usize,
+    max_distance: f64,
+    metric: Metric,
+) -> Vec<(usize, f64)> {
+    let env = x.envelope();
+    let mut res = Vec::with_capacity(k.min(tree.size()));
+    let mut queue = BinaryHeap::new();
+
+    for child in tree.root().children() {
+        queue.push(QueueItem {
+            dist: metric.lower_bound(&env, &child.envelope()),
+            item: Candidate::Node(child),
+        });
+    }
+
+    while let Some(QueueItem { dist, item }) = queue.pop() {
+        if res.len() >= k || dist > max_distance {
+            break;
+        }
+
+        match item {
+            Candidate::Leaf(i) => res.push((i, dist)),
+            Candidate::Node(RTreeNode::Parent(parent)) => {
+                for child in parent.children() {
+                    queue.push(QueueItem {
+                        dist: metric.lower_bound(&env, &child.envelope()),
+                        item: Candidate::Node(child),
+                    });
+                }
+            }
+            Candidate::Node(RTreeNode::Leaf(leaf)) => queue.push(QueueItem {
+                dist: metric.distance(&x.geom, &leaf.geom().geom),
+                item: Candidate::Leaf(leaf.data),
+            }),
+        }
+    }
+
+    res
+}
+
+// A spatial index that persists between calls from R
+pub struct SpatialIndex {
+    pub tree: GeomTree,
+    pub n: usize,
+}
+
+impl std::fmt::Debug for SpatialIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "SpatialIndex {{ n: {} }}", self.n)
+    }
+}
+
+#[extendr]
+fn rs_index_(x: List) -> Robj {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let n = x.len();
+    let index = SpatialIndex {
+        tree: create_cached_rtree(x),
+        n,
+    };
+
+    let mut res = ExternalPtr::new(index).into_robj();
+    res.set_attrib("class", "rs_index").unwrap();
+    res.set_attrib("size", n as i32).unwrap();
+    res
+}
+
+/// Extract the spatial index from an `rs_index` object
+pub fn index_from_robj(x: &Robj) -> ExternalPtr<SpatialIndex> {
+    if !x.inherits("rs_index") {
+        panic!("`index` must be of class `rs_index`")
+    }
+
+    // external pointers are null after being serialized
+    if unsafe { R_ExternalPtrAddr(x.get()) }.is_null() {
+        panic!("`index` is no longer valid. It must be recreated with `rs_index()`")
+    }
+
+    ExternalPtr::<SpatialIndex>::try_from(x.clone())
+        .unwrap_or_else(|_| panic!("`index` must be of class `rs_index`"))
+}
+
+// runs a query for each geometry in `y`. Envelope, point, and distance queries
+// are sorted by position while nearest neighbors are ordered by distance
+fn query_index<F>(y: List, f: F) -> List
+where
+    F: Fn(&Geom) -> Vec<i32> + Sync,
+{
+    if !y.inherits("rsgeo") {
+        panic!("`y` must be of class `rsgeo`")
+    }
+
+    let res = geometry_from_list(y)
+        .into_par_iter()
+        .map(|yi| match yi {
+            Some(yi) => f(&Geom::from(yi)),
+            None => vec![],
+        })
+        .collect::<Vec<Vec<i32>>>();
+
+    List::from_values(res)
+}
+
+#[extendr]
+fn index_envelope_(index: Robj, y: List) -> List {
+    let index = index_from_robj(&index);
+    let tree = &index.tree;
+
+    query_index(y, |yi| {
+        let mut ids = tree
+            .locate_in_envelope_intersecting(&yi.envelope())
+            .map(|cnd| cnd.data as i32 + 1)
+            .collect::<Vec<i32>>();
+
+        ids.sort_unstable();
+        ids
+    })
+}
+
+#[extendr]
+fn index_point_(index: Robj, y: List) -> List {
+    if !y.inherits("rs_POINT") {
+        panic!("`y` must be of class `rs_POINT`")
+    }
+
+    let index = index_from_robj(&index);
+    let tree = &index.tree;
+
+    query_index(y, |yi| {
+        let pnt = match yi.geom {
+            Geometry::Point(p) => p,
+            _ => unreachable!(),
+        };
+
+        let mut ids = tree
+            .locate_in_envelope_intersecting(&AABB::from_point([pnt.x(), pnt.y()]))
+            .filter(|cnd| cnd.geom().geom.intersects(&pnt))
+            .map(|cnd| cnd.data as i32 + 1)
+            .collect::<Vec<i32>>();
+
+        ids.sort_unstable();
+        ids
+    })
+}
+
+#[extendr]
+fn index_nearest_(index: Robj, y: List, k: i32) -> List {
+    let index = index_from_robj(&index);
+    let tree = &index.tree;
+
+    query_index(y, |yi| {
+        nearest_k(tree, yi, k as usize, f64::INFINITY, Metric::Euclidean)
+            .into_iter()
+            .map(|(i, _)| i as i32 + 1)
+            .collect()
+    })
+}
+
+#[extendr]
+fn index_within_distance_(index: Robj, y: List, distance: f64) -> List {
+    let index = index_from_robj(&index);
+    let tree = &index.tree;
+
+    query_index(y, |yi| {
+        // candidates are those whose envelope is within the distance of the
+        // query envelope
+        let env = yi.envelope();
+        let (lower, upper) = (env.lower(), env.upper());
+        let search = AABB::from_corners(
+            [lower[0] - distance, lower[1] - distance],
+            [upper[0] + distance, upper[1] + distance],
+        );
+
+        let mut ids = tree
+            .locate_in_envelope_intersecting(&search)
+            .filter(|cnd| yi.geom.euclidean_distance(&cnd.geom().geom) <= distance)
+            .map(|cnd| cnd.data as i32 + 1)
+            .collect::<Vec<i32>>();
+
+        ids.sort_unstable();
+        ids
+    })
+}
+
+#[extendr]
+fn nearest_neighbors_(x: List, y: List, k: i32, max_distance: f64, haversine: bool) -> List {
+    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
+        panic!("`x` and `y` must be of class `rsgeo`")
+    }
+
+    let metric = if haversine {
+        if !x.inherits("rs_POINT") || !y.inherits("rs_POINT") {
+            panic!("`x` and `y` must be of class `rs_POINT` to use haversine distance")
+        }
+        Metric::Haversine
+    } else {
+        Metric::Euclidean
+    };
+
+    let tree = create_cached_rtree(y);
+
+    let (index, distance): (Vec<Vec<i32>>, Vec<Vec<f64>>) = geometry_from_list(x)
+        .into_par_iter()
+        .map(|xi| -> (Vec<i32>, Vec<f64>) {
+            match xi {
+                Some(xi) => nearest_k(&tree, &Geom::from(xi), k as usize, max_distance, metric)
+                    .into_iter()
+                    .map(|(i, d)| (i as i32 + 1, d))
+                    .unzip(),
+                None => (vec![], vec![]),
+            }
+        })
+        .unzip();
+
+    list!(
+        index = List::from_values(index),
+        distance = List::from_values(distance)
+    )
+}
+
+// Neighbors of each geometry in `x` among the other geometries in `x`. At
+// most `k` neighbors within `max_distance` are found. `k` is infinite for
+// distance band neighbors
+#[extendr]
+fn neighbors_(x: List, k: f64, max_distance: f64, haversine: bool) -> List {
+    if !x.inherits("rsgeo") {
+        panic!("`x` must be of class `rsgeo`")
+    }
+
+    let metric = if haversine {
+        if !x.inherits("rs_POINT") {
+            panic!("`x` must be of class `rs_POINT` to use haversine distance")
+        }
+        Metric::Haversine
+    } else {
+        Metric::Euclidean
+    };
+
+    let k = if k.is_infinite() {
+        usize::MAX
+    } else {
+        k as usize
+    };
+
+    let tree = create_cached_rtree(x.clone());
+
+    let (index, distance): (Vec<Vec<i32>>, Vec<Vec<f64>>) = geometry_from_list(x)
+        .into_par_iter()
+        .enumerate()
+        .map(|(i, xi)| -> (Vec<i32>, Vec<f64>) {
+            // one more is searched for because each geometry is its own
+            // nearest neighbor
+            match xi {
+                Some(xi) => nearest_k(
+                    &tree,
+                    &Geom::from(xi),
+                    k.saturating_add(1),
+                    max_distance,
+                    metric,
+                )
+                .into_iter()
+                .filter(|(j, _)| *j != i)
+                .take(k)
+                .map(|(j, d)| (j as i32 + 1, d))
+                .unzip(),
+                None => (vec![], vec![]),
+            }
+        })
+        .unzip();
+
+    list!(
+        index = List::from_values(index),
+        distance = List::from_values(distance)
+    )
+}
+
 extendr_module! {
     mod spatial_index;
+    fn rs_index_;
+    fn index_envelope_;
+    fn index_point_;
+    fn index_nearest_;
+    fn index_within_distance_;
+    fn nearest_neighbors_;
+    fn neighbors_;
 }
-
-// use std::rc::Rc;
-
-// Creates a spatial index from a list
-//(RTree<GeomWithData<Geom, usize>>, Rc<Vec<GeomWithData<Geom, usize>>>)
-// pub fn create_rtree(geoms: List) -> RTree<GeomWithData<Geom, usize>> {
-//     // Class checking
-//     let cls = geoms.class().unwrap().next().unwrap();
-//     if !cls.starts_with("rs_") {
-//         panic!("`x` must be a Rust geometry type")
-//     }
-
-//     let all_geoms = geoms
-//         .iter()
-//         .enumerate()
-//         .filter_map(|(i, (_, xi))| {
-//             if xi.is_null() {
-//                 None
-//             } else {
-//                 let geo = Geom::try_from(xi).unwrap();
-//                 Some(GeomWithData::new(geo, i))
-//             }
-//         })
-//         .collect::<Vec<GeomWithData<Geom, usize>>>();
-
-//     //let all_geoms = Rc::new(all_geoms);
-//     // (RTree::bulk_load(all_geoms.to_vec()), all_geoms)
-//     RTree::bulk_load(all_geoms.to_vec())
-// }
-
-// // related:
-// // https://github.com/georust/rstar/issues/108
-// // https://github.com/georust/geo/issues/982
-// pub fn create_rtree(geoms: Vec<Geom>) -> RTree<GeomWithData<Geom, usize>> {
-//     let mut r_tree = RTree::new();
-//     for (index, geom) in geoms.into_iter().enumerate() {
-//         let geom = GeomWithData::new(geom, index);
-//         r_tree.insert(geom);
-//     }
-//     r_tree
-// }
-
-// #[extendr]
-// /// Create an rstar RTree from a vector of geometry
-// /// @param x a vector of rust geometry
-// /// @export
-// fn rstar_rtree(x: List) -> Robj {
-//     let geoms = from_list(x);
-
-//     let rtree = create_rtree(geoms);
-//     let rtree_size = rtree.size();
-
-//     ExternalPtr::new(rtree)
-//         .as_robj()
-//         .set_attrib("class", "rstar_rtree")
-//         .unwrap()
-//         .set_attrib("size", rtree_size)
-//         .unwrap()
-// }
-
-// #[extendr]
-// fn intersection_candidates(x: List, y: List) -> List {
-//     let x = from_list(x);
-//     let n = x.len();
-//     let y = from_list(y);
-
-//     let x_rtree = create_rtree(x);
-//     let y_rtree = create_rtree(y);
-
-//     let cands = x_rtree.intersection_candidates_with_other_tree(&y_rtree);
-//     // this is a vector of tuples (lhs index, rhs index)
-//     let res_cands = cands
-//         .map(|(x, y)| (x.data, y.data))
-//         .collect::<Vec<(usize, usize)>>();
-
-//     // need to create a sparse representation now
-//     let mut res: Vec<Vec<i32>> = Vec::with_capacity(n);
-
-//     // allocate internal vecs
-//     for _ in 0..n {
-//         res.push(Vec::with_capacity(n))
-//     }
-
-//     for (xin, yin) in res_cands.into_iter() {
-//         //if yin == 0 { continue; }
-//         res[xin].push(yin as i32)
-//     }
-
-//     List::from_values(res)
-// }
-
-// // helper to craft the AABB this is an alternative to a
-// // from trait becuase i dont know the types here
-// fn to_aabb(x: &Geom) -> AABB<[f64; 2]> {
-//     let rct = x.geom.bounding_rect().unwrap();
-//     let ll = rct.min();
-//     let ur = rct.max();
-//     AABB::from_corners(ll.into(), ur.into())
-// }
-
-// #[extendr]
-// fn print_aabb(x: Robj) {
-//     let x = Geom::from(x);
-//     let aabb = to_aabb(&x);
-//     rprintln!("{:?}", aabb);
-// }
-
-// #[extendr]
-// fn locate_in_envelope(rtree: Robj, geom: Robj) -> Integers {
-//     let rtree: ExternalPtr<RTree<GeomWithData<Geom, usize>>> = rtree.try_into().unwrap();
-//     let env = to_aabb(&geom.into());
-
-//     let res = rtree.locate_in_envelope(&env);
-
-//     res.map(|x| Rint::from(x.data as i32)).collect::<Integers>()
-// }
-
-// fn locate_nearest_nbs(rtree: Robj, geom: Robj) -> Integers {
-//     let rtree: ExternalPtr<RTree<GeomWithData<Geom, usize>>> = rtree.try_into().unwrap();
-//     let env = to_aabb(&geom.into());
-
-//     let tree = *rtree;
-//     tree.near
-
-// }
-
-// This is how i would do queen contiguity but intersects is just so friggin slow
-// #[extendr]
-// fn queen_contiguity(x: List) -> List {
-//     let x = from_list(x);
-//     let geoms = x.clone(); // clone for the R tree
-//     let n = geoms.len();
-
-//     let mut r_tree = RTree::new();
-//     for (index, geom) in geoms.into_iter().enumerate() {
-//         let geom = GeomWithData::new(geom, index);
-//         r_tree.insert(geom);
-//     }
-
-//     let cands = r_tree.intersection_candidates_with_other_tree(&r_tree);
-//     // this is a vector of tuples (lhs index, rhs index)
-//     let res_cands = cands
-//         .map(|(x, y)| (x.data, y.data))
-//         .collect::<Vec<(usize, usize)>>();
-
-//     // need to create a sparse representation now
-//     let mut res: Vec<Vec<i32>> = Vec::with_capacity(n);
-
-//     // allocate internal vecs
-//     for _ in 0..n {
-//         res.push(Vec::with_capacity(n))
-//     }
-
-//     for (xin, yin) in res_cands.into_iter() {
-//         //if yin == 0 { continue; }
-//         if x[xin].geom.intersects(&x[yin].geom) { res[xin].push((yin as i32) + 1) }
-//     }
-
-//     List::from_values(res)
-
-// }
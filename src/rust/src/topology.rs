@@ -1,12 +1,17 @@
 use extendr_api::prelude::*;
-use geo::{Contains, Intersects, Within};
-
-use crate::spatial_index::create_cached_rtree;
-use rstar::RTreeObject;
+use geo::coordinate_position::CoordPos;
+use geo::dimensions::{Dimensions, HasDimensions};
+use geo::relate::IntersectionMatrix;
+use geo::{Contains, Intersects, Relate, Within};
+use geo_types::{Coord, Geometry, Polygon};
+
+use crate::join::{sparse_join, Candidate, SearchEnvelope};
+use crate::spatial_index::Metric;
+use rstar::{Envelope, RTreeObject};
 use sfconversions::{geometry_from_list, Geom};
 
 use rayon::prelude::*;
-use std::sync::Mutex;
+use std::str::FromStr;
 
 #[extendr]
 /// Binary Predicates
@@ -15,8 +20,22 @@ use std::sync::Mutex;
 /// two geometry vectors. Binary predicates are provided both pairwise
 /// as a sparse matrix.
 ///
+/// @details
+///
+/// The predicates follow the definitions of the OGC Simple Features
+/// specification. `equals_topo` tests if two geometries are topologically
+/// equal regardless of the order of their coordinates. `equals_exact` tests
+/// if two geometries are the same type with the same structure and each pair
+/// of coordinates is within `tolerance` of each other.
+///
+/// Sparse predicates use a spatial index to only test geometries whose
+/// bounding boxes intersect. `disjoint_sparse()` returns every geometry in
+/// `y` that is not found to intersect.
+///
 /// @param x an object of class `rsgeo`
 /// @param y an object of class `rsgeo`
+/// @param tolerance the maximum distance between coordinates for them to be
+/// considered equal. A scalar numeric.
 ///
 /// @export
 /// @rdname topology
@@ -39,117 +58,56 @@ use std::sync::Mutex;
 ///     # within
 ///     within_sparse(x, y)
 ///     within_pairwise(x, y)
+///     # touches
+///     touches_sparse(x, y)
+///     touches_pairwise(x, y)
+///     # disjoint
+///     disjoint_sparse(x, y)
+///     # equals
+///     equals_topo_pairwise(x, rev(y))
+///     equals_exact_pairwise(x, rev(y), 0)
 /// }
 /// @returns
-/// - For `_sparse` a list of integer vectors containing the position
-/// of the geometry in `y`
+/// - For `_sparse` a list with an integer vector for each geometry in `x`
+/// containing the positions of the geometries in `y` for which `x[i]`
+/// satisfies the predicate with `y[j]`. The exceptions are `contains_sparse()`,
+/// which returns the geometries in `y` that contain `x[i]`, and
+/// `within_sparse()`, which returns the geometries in `y` that are within
+/// `x[i]`. That is, `j` is in `contains_sparse(x, y)[[i]]` when
+/// `contains_pairwise(y[j], x[i])` is `TRUE`.
 ///
 /// - For `_pairwise` a logical vector
 fn intersects_sparse(x: List, y: List) -> List {
-    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
-        panic!("`x` and `y` must be of class `rsgeo`")
-    }
-
-    let n = x.len();
-    let xtree = create_cached_rtree(x);
-
-    let y = geometry_from_list(y);
-    let index = Mutex::new(vec![Vec::with_capacity(n); n]);
-
-    y.into_par_iter().enumerate().for_each(|(i, yi)| {
-        if let Some(yi) = yi {
-            let yi = Geom::from(yi);
-            let env = yi.envelope();
-            let cands = xtree.locate_in_envelope_intersecting(&env);
-
-            cands.for_each(|cnd| {
-                if yi.geom.intersects(&cnd.geom().geom) {
-                    let mut ind = index.lock().unwrap();
-                    ind[cnd.data].push((i as i32) + 1)
-                }
-            })
-        }
-    });
-
-    let mut index = index.into_inner().unwrap();
-
-    // we sort the results because the order will be different
-    // each and every time. we need the result to be reproducible
-    index.par_iter_mut().for_each(|xi| xi.sort());
-
-    List::from_values(index)
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.intersects(yi),
+    ))
 }
 
 #[extendr]
 /// @export
 /// @rdname topology
 fn contains_sparse(x: List, y: List) -> List {
-    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
-        panic!("`x` and `y` must be of class `rsgeo`")
-    }
-
-    let n = x.len();
-    let xtree = create_cached_rtree(x);
-
-    let y = geometry_from_list(y);
-    let index = Mutex::new(vec![Vec::with_capacity(n); n]);
-
-    y.into_par_iter().enumerate().for_each(|(i, yi)| {
-        if let Some(yi) = yi {
-            let yi = Geom::from(yi);
-            let env = yi.envelope();
-            let cands = xtree.locate_in_envelope_intersecting(&env);
-
-            cands.for_each(|cnd| {
-                if yi.geom.contains(&cnd.geom().geom) {
-                    let mut ind = index.lock().unwrap();
-                    ind[cnd.data].push((i as i32) + 1)
-                }
-            })
-        }
-    });
-
-    let mut index = index.into_inner().unwrap();
-
-    index.par_iter_mut().for_each(|xi| xi.sort());
-
-    List::from_values(index)
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| yi.contains(xi.geom),
+    ))
 }
 
 #[extendr]
 /// @export
 /// @rdname topology
 fn within_sparse(x: List, y: List) -> List {
-    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
-        panic!("`x` and `y` must be of class `rsgeo`")
-    }
-
-    let n = x.len();
-    let xtree = create_cached_rtree(x);
-
-    let y = geometry_from_list(y);
-    let index = Mutex::new(vec![Vec::with_capacity(n); n]);
-
-    y.into_par_iter().enumerate().for_each(|(i, yi)| {
-        if let Some(yi) = yi {
-            let yi = Geom::from(yi);
-            let env = yi.envelope();
-            let cands = xtree.locate_in_envelope_intersecting(&env);
-
-            cands.for_each(|cnd| {
-                if yi.geom.is_within(&cnd.geom().geom) {
-                    let mut ind = index.lock().unwrap();
-                    ind[cnd.data].push((i as i32) + 1)
-                }
-            })
-        }
-    });
-
-    let mut index = index.into_inner().unwrap();
-
-    index.par_iter_mut().for_each(|xi| xi.sort());
-
-    List::from_values(index)
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| yi.is_within(xi.geom),
+    ))
 }
 
 #[extendr]
@@ -242,9 +200,323 @@ fn within_pairwise(x: List, y: List) -> Logicals {
     Logicals::from_values(res)
 }
 
-// TODO
-// disjoint
-// DE-9IM relates
+// Tests `f(x, y)` for every pair whose envelopes intersect after the
+// envelope of `y` is expanded by `search` using the sparse join engine
+fn predicate_sparse<F>(x: List, y: List, search: SearchEnvelope, f: F) -> Vec<Vec<i32>>
+where
+    F: Fn(&Candidate, &Geometry) -> bool + Sync,
+{
+    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
+        panic!("`x` and `y` must be of class `rsgeo`")
+    }
+
+    sparse_join(x, y, search, f)
+}
+
+// Tests `f(x, y)` for each pair of geometries. Missing geometries are NA
+fn predicate_pairwise<F>(x: List, y: List, f: F) -> Logicals
+where
+    F: Fn(&Geometry, &Geometry) -> bool + Sync,
+{
+    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
+        panic!("`x` and `y` must be of class `rsgeo`")
+    }
+
+    let x = geometry_from_list(x);
+    let y = geometry_from_list(y);
+
+    let res = x
+        .into_par_iter()
+        .zip(y.into_par_iter())
+        .map(|(xi, yi)| match (xi, yi) {
+            (Some(xi), Some(yi)) => Some(f(&xi, &yi)),
+            _ => None,
+        })
+        .collect::<Vec<Option<bool>>>();
+
+    Logicals::from_values(res)
+}
+
+// Two geometries are exactly equal if they are the same type with the same
+// structure and each pair of coordinates is within `tolerance`
+fn equals_exact(x: &Geometry, y: &Geometry, tolerance: f64) -> bool {
+    fn coords_equal(x: &[Coord], y: &[Coord], tolerance: f64) -> bool {
+        x.len() == y.len()
+            && x.iter()
+                .zip(y.iter())
+                .all(|(a, b)| (a.x - b.x).hypot(a.y - b.y) <= tolerance)
+    }
+
+    fn polygons_equal(x: &Polygon, y: &Polygon, tolerance: f64) -> bool {
+        coords_equal(&x.exterior().0, &y.exterior().0, tolerance)
+            && x.interiors().len() == y.interiors().len()
+            && x.interiors()
+                .iter()
+                .zip(y.interiors().iter())
+                .all(|(a, b)| coords_equal(&a.0, &b.0, tolerance))
+    }
+
+    match (x, y) {
+        (Geometry::Point(a), Geometry::Point(b)) => coords_equal(&[a.0], &[b.0], tolerance),
+        (Geometry::Line(a), Geometry::Line(b)) => {
+            coords_equal(&[a.start, a.end], &[b.start, b.end], tolerance)
+        }
+        (Geometry::LineString(a), Geometry::LineString(b)) => coords_equal(&a.0, &b.0, tolerance),
+        (Geometry::Polygon(a), Geometry::Polygon(b)) => polygons_equal(a, b, tolerance),
+        (Geometry::MultiPoint(a), Geometry::MultiPoint(b)) => {
+            let a = a.0.iter().map(|p| p.0).collect::<Vec<Coord>>();
+            let b = b.0.iter().map(|p| p.0).collect::<Vec<Coord>>();
+            coords_equal(&a, &b, tolerance)
+        }
+        (Geometry::MultiLineString(a), Geometry::MultiLineString(b)) => {
+            a.0.len() == b.0.len()
+                && a.0
+                    .iter()
+                    .zip(b.0.iter())
+                    .all(|(a, b)| coords_equal(&a.0, &b.0, tolerance))
+        }
+        (Geometry::MultiPolygon(a), Geometry::MultiPolygon(b)) => {
+            a.0.len() == b.0.len()
+                && a.0
+                    .iter()
+                    .zip(b.0.iter())
+                    .all(|(a, b)| polygons_equal(a, b, tolerance))
+        }
+        (Geometry::Rect(a), Geometry::Rect(b)) => {
+            coords_equal(&[a.min(), a.max()], &[b.min(), b.max()], tolerance)
+        }
+        (Geometry::Triangle(a), Geometry::Triangle(b)) => {
+            coords_equal(&a.to_array(), &b.to_array(), tolerance)
+        }
+        (Geometry::GeometryCollection(a), Geometry::GeometryCollection(b)) => {
+            a.0.len() == b.0.len()
+                && a.0
+                    .iter()
+                    .zip(b.0.iter())
+                    .all(|(a, b)| equals_exact(a, b, tolerance))
+        }
+        _ => false,
+    }
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn disjoint_sparse(x: List, y: List) -> List {
+    // the index finds the intersecting geometries and every other
+    // non-missing geometry in `y` is disjoint
+    let x_missing = x.iter().map(|(_, xi)| xi.is_null()).collect::<Vec<bool>>();
+
+    let y_present = y
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, yi))| !yi.is_null())
+        .map(|(j, _)| (j as i32) + 1)
+        .collect::<Vec<i32>>();
+
+    let intersecting = predicate_sparse(x, y, SearchEnvelope::Intersecting, |xi, yi| {
+        xi.intersects(yi)
+    });
+
+    let index = intersecting
+        .into_par_iter()
+        .zip(x_missing.into_par_iter())
+        .map(|(hits, missing)| {
+            if missing {
+                return vec![];
+            }
+
+            y_present
+                .iter()
+                .filter(|j| hits.binary_search(*j).is_err())
+                .copied()
+                .collect::<Vec<i32>>()
+        })
+        .collect::<Vec<Vec<i32>>>();
+
+    List::from_values(index)
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn disjoint_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| !xi.intersects(yi))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn touches_sparse(x: List, y: List) -> List {
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.geom.relate(yi).is_touches(),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn touches_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).is_touches())
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn crosses_sparse(x: List, y: List) -> List {
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.geom.relate(yi).is_crosses(),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn crosses_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).is_crosses())
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn overlaps_sparse(x: List, y: List) -> List {
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.geom.relate(yi).is_overlaps(),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn overlaps_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).is_overlaps())
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn covers_sparse(x: List, y: List) -> List {
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.geom.relate(yi).is_covers(),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn covers_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).is_covers())
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn covered_by_sparse(x: List, y: List) -> List {
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.geom.relate(yi).is_coveredby(),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn covered_by_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).is_coveredby())
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn equals_topo_sparse(x: List, y: List) -> List {
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Intersecting,
+        |xi, yi| xi.geom.relate(yi).is_equal_topo(),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn equals_topo_pairwise(x: List, y: List) -> Logicals {
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).is_equal_topo())
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn equals_exact_sparse(x: List, y: List, tolerance: f64) -> List {
+    if tolerance.is_nan() || tolerance < 0.0 {
+        panic!("`tolerance` must be a non-negative number")
+    }
+
+    List::from_values(predicate_sparse(
+        x,
+        y,
+        SearchEnvelope::Planar(tolerance),
+        |xi, yi| equals_exact(xi.geom, yi, tolerance),
+    ))
+}
+
+#[extendr]
+/// @export
+/// @rdname topology
+fn equals_exact_pairwise(x: List, y: List, tolerance: f64) -> Logicals {
+    if tolerance.is_nan() || tolerance < 0.0 {
+        panic!("`tolerance` must be a non-negative number")
+    }
+
+    predicate_pairwise(x, y, |xi, yi| equals_exact(xi, yi, tolerance))
+}
+
+// Validates the arguments of the within distance predicates and returns the
+// distance metric along with how envelopes are expanded to find candidates
+fn distance_search(x: &List, y: &List, distance: f64, haversine: bool) -> (Metric, SearchEnvelope) {
+    if distance.is_nan() || distance < 0.0 {
+        panic!("`distance` must be a non-negative number")
+    }
+
+    if haversine {
+        if !x.inherits("rs_POINT") || !y.inherits("rs_POINT") {
+            panic!("`x` and `y` must be of class `rs_POINT` to use haversine distance")
+        }
+        (Metric::Haversine, SearchEnvelope::Haversine(distance))
+    } else {
+        (Metric::Euclidean, SearchEnvelope::Planar(distance))
+    }
+}
+
+#[extendr]
+fn within_distance_sparse_(x: List, y: List, distance: f64, haversine: bool) -> List {
+    let (metric, search) = distance_search(&x, &y, distance, haversine);
+
+    List::from_values(predicate_sparse(x, y, search, |xi, yi| {
+        metric.distance(xi.geom, yi) <= distance
+    }))
+}
+
+#[extendr]
+fn within_distance_pairwise_(x: List, y: List, distance: f64, haversine: bool) -> Logicals {
+    let (metric, _) = distance_search(&x, &y, distance, haversine);
+
+    predicate_pairwise(x, y, |xi, yi| metric.distance(xi, yi) <= distance)
+}
 
 // relates use something called DE-9IM
 // its an intersection matrix between two geometries.
@@ -257,7 +529,207 @@ fn within_pairwise(x: List, y: List) -> Logicals {
 // F: false, or none
 // 0: a single point
 // 1: a shared line
-// 3: an area intersection
+// 2: an area intersection
+
+const POSITIONS: [CoordPos; 3] = [CoordPos::Inside, CoordPos::OnBoundary, CoordPos::Outside];
+
+fn dimension_char(x: Dimensions) -> char {
+    match x {
+        Dimensions::Empty => 'F',
+        Dimensions::ZeroDimensional => '0',
+        Dimensions::OneDimensional => '1',
+        Dimensions::TwoDimensional => '2',
+    }
+}
+
+// the 9 character string representation of an intersection matrix
+fn matrix_string(x: &IntersectionMatrix) -> String {
+    POSITIONS
+        .iter()
+        .flat_map(|a| POSITIONS.iter().map(|b| dimension_char(x.get(*a, *b))))
+        .collect()
+}
+
+// The intersection matrix of two geometries that do not intersect can be
+// found from their dimensions alone. Their interiors and boundaries only
+// intersect the exterior of the other geometry.
+fn disjoint_matrix(x: &Geometry, y: &Geometry) -> IntersectionMatrix {
+    let spec = [
+        'F',
+        'F',
+        dimension_char(x.dimensions()),
+        'F',
+        'F',
+        dimension_char(x.boundary_dimensions()),
+        dimension_char(y.dimensions()),
+        dimension_char(y.boundary_dimensions()),
+        '2',
+    ]
+    .iter()
+    .collect::<String>();
+
+    IntersectionMatrix::from_str(&spec).unwrap()
+}
+
+fn check_pattern(pattern: &str) {
+    let valid = pattern.len() == 9
+        && pattern
+            .chars()
+            .all(|c| matches!(c, '0' | '1' | '2' | 'T' | 't' | 'F' | 'f' | '*'));
+
+    if !valid {
+        panic!("`pattern` must be a 9 character DE-9IM pattern of `0`, `1`, `2`, `T`, `F`, or `*`")
+    }
+}
+
+// patterns that require the interiors or boundaries of the geometries to
+// intersect can only match geometries whose envelopes intersect
+fn pattern_requires_intersection(pattern: &str) -> bool {
+    pattern
+        .chars()
+        .enumerate()
+        .filter(|(i, _)| matches!(i, 0 | 1 | 3 | 4))
+        .any(|(_, c)| matches!(c, '0' | '1' | '2' | 'T' | 't'))
+}
+
+#[extendr]
+/// DE-9IM Relations
+///
+/// Compute the [DE-9IM](https://en.wikipedia.org/wiki/DE-9IM) intersection
+/// matrix between two geometry vectors or test it against a pattern.
+///
+/// @details
+///
+/// The intersection matrix describes the dimension of the intersections
+/// between the interior, boundary, and exterior of `x` and `y`. It is
+/// represented as a 9 character string in row-major order where `F` is no
+/// intersection and `0`, `1`, and `2` are point, line, and area intersections
+/// respectively.
+///
+/// A `pattern` is a 9 character string which may additionally use `T` to
+/// match any intersection and `*` to match anything. For example
+/// `"T*F**F***"` is the pattern for `x` within `y`.
+///
+/// `relate_pattern_sparse()` only relates geometries whose bounding boxes
+/// intersect unless the pattern can be matched by disjoint geometries.
+///
+/// @param x an object of class `rsgeo`
+/// @param y an object of class `rsgeo`
+/// @param pattern a DE-9IM pattern. A scalar character.
+///
+/// @export
+/// @rdname relate
+/// @examples
+/// x <- geom_polygon(c(0, 2, 2, 0, 0), c(0, 0, 2, 2, 0))
+/// y <- geom_polygon(
+///   x = c(1, 3, 3, 1, 1, 0.5, 1.5, 1.5, 0.5, 0.5),
+///   y = c(1, 1, 3, 3, 1, 0.5, 0.5, 1.5, 1.5, 0.5),
+///   id = rep(1:2, each = 5)
+/// )
+/// relate_pairwise(rep(x, 2), y)
+/// relate_pattern_sparse(x, y, "T*F**F***")
+/// relate_pattern_pairwise(rep(x, 2), y, "T*T***T**")
+/// @returns
+/// - `relate_pairwise()` a character vector of intersection matrices
+/// - `relate_pattern_sparse()` a list of integer vectors containing the
+/// position of the geometries in `y` that match the pattern
+/// - `relate_pattern_pairwise()` a logical vector
+fn relate_pairwise(x: List, y: List) -> Strings {
+    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
+        panic!("`x` and `y` must be of class `rsgeo`")
+    }
+
+    let x = geometry_from_list(x);
+    let y = geometry_from_list(y);
+
+    let res = x
+        .into_par_iter()
+        .zip(y.into_par_iter())
+        .map(|(xi, yi)| match (xi, yi) {
+            (Some(xi), Some(yi)) => Some(matrix_string(&xi.relate(&yi))),
+            _ => None,
+        })
+        .collect::<Vec<Option<String>>>();
+
+    let res_vec = res
+        .into_iter()
+        .map(|xi| match xi {
+            Some(xi) => Rstr::from(xi),
+            None => Rstr::na(),
+        })
+        .collect::<Vec<Rstr>>();
+
+    Strings::from_values(res_vec)
+}
+
+#[extendr]
+/// @export
+/// @rdname relate
+fn relate_pattern_sparse(x: List, y: List, pattern: &str) -> List {
+    if !x.inherits("rsgeo") || !y.inherits("rsgeo") {
+        panic!("`x` and `y` must be of class `rsgeo`")
+    }
+
+    check_pattern(pattern);
+
+    if pattern_requires_intersection(pattern) {
+        let index = predicate_sparse(x, y, SearchEnvelope::Intersecting, |xi, yi| {
+            xi.geom.relate(yi).matches(pattern).unwrap()
+        });
+
+        List::from_values(index)
+    } else {
+        // disjoint geometries can match the pattern so every pair is
+        // checked. The full relate is only computed when envelopes intersect
+        let x = geometry_from_list(x)
+            .into_iter()
+            .map(|xi| xi.map(Geom::from))
+            .collect::<Vec<Option<Geom>>>();
+        let y = geometry_from_list(y)
+            .into_iter()
+            .map(|yi| yi.map(Geom::from))
+            .collect::<Vec<Option<Geom>>>();
+
+        let index = x
+            .par_iter()
+            .map(|xi| {
+                let Some(xi) = xi else {
+                    return vec![];
+                };
+
+                let xenv = xi.envelope();
+
+                y.iter()
+                    .enumerate()
+                    .filter_map(|(j, yj)| {
+                        let yj = yj.as_ref()?;
+                        let im = if xenv.intersects(&yj.envelope()) {
+                            xi.geom.relate(&yj.geom)
+                        } else {
+                            disjoint_matrix(&xi.geom, &yj.geom)
+                        };
+
+                        if im.matches(pattern).unwrap() {
+                            Some((j as i32) + 1)
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<Vec<i32>>()
+            })
+            .collect::<Vec<Vec<i32>>>();
+
+        List::from_values(index)
+    }
+}
+
+#[extendr]
+/// @export
+/// @rdname relate
+fn relate_pattern_pairwise(x: List, y: List, pattern: &str) -> Logicals {
+    check_pattern(pattern);
+    predicate_pairwise(x, y, |xi, yi| xi.relate(yi).matches(pattern).unwrap())
+}
 
 extendr_module! {
     mod topology;
@@ -267,30 +739,25 @@ extendr_module! {
     fn contains_pairwise;
     fn within_sparse;
     fn within_pairwise;
+    fn disjoint_sparse;
+    fn disjoint_pairwise;
+    fn touches_sparse;
+    fn touches_pairwise;
+    fn crosses_sparse;
+    fn crosses_pairwise;
+    fn overlaps_sparse;
+    fn overlaps_pairwise;
+    fn covers_sparse;
+    fn covers_pairwise;
+    fn covered_by_sparse;
+    fn covered_by_pairwise;
+    fn equals_topo_sparse;
+    fn equals_topo_pairwise;
+    fn equals_exact_sparse;
+    fn equals_exact_pairwise;
+    fn within_distance_sparse_;
+    fn within_distance_pairwise_;
+    fn relate_pairwise;
+    fn relate_pattern_sparse;
+    fn relate_pattern_pairwise;
 }
-
-// This approach is generally slow it works by building two R* trees.
-// using a cached envelope is faster than using a  computer bounding box so we
-// opt to using the cached variant
-// #[extendr]
-// fn intersects_sparse_cached2(x: List, y: List) -> List {
-//     let n = x.len();
-//     let xtree  = create_cached_rtree(x);
-
-//     let ytree = create_cached_rtree(y);
-
-//     let candidates = xtree.intersection_candidates_with_other_tree(&ytree);
-
-//     let mut index = vec![Vec::with_capacity(n); n];
-
-//     for (x, y) in candidates {
-//         let xid = x.data;
-//         let yid = y.data;
-//         let res = x.geom().geom.intersects(&y.geom().geom);
-//         if res {
-//             index[xid].push((yid as i32) + 1i32)
-//         }
-//     }
-//     List::from_values(index)
-
-// }
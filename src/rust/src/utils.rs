@@ -1,4 +1,5 @@
 use extendr_api::prelude::*;
+use geo_types::Geometry;
 use sfconversions::Geom;
 // Create a blank pointer to be used in ptype casting
 
@@ -12,6 +13,48 @@ fn print_geom(x: Robj) -> String {
     Geom::from(x).print().into()
 }
 
+// Identify the `rsgeo` class for a vector of geometries. If every non-missing
+// geometry is the same type we use that type otherwise it is a generic
+// `geometry` vector.
+pub fn infer_geom_type(x: &[Option<Geometry>]) -> &'static str {
+    let mut types = x.iter().flatten().map(|xi| match xi {
+        Geometry::Point(_) => "point",
+        Geometry::MultiPoint(_) => "multipoint",
+        Geometry::Line(_) | Geometry::LineString(_) => "linestring",
+        Geometry::MultiLineString(_) => "multilinestring",
+        Geometry::Polygon(_) | Geometry::Rect(_) | Geometry::Triangle(_) => "polygon",
+        Geometry::MultiPolygon(_) => "multipolygon",
+        Geometry::GeometryCollection(_) => "geometrycollection",
+    });
+
+    match types.next() {
+        Some(first) => {
+            if types.all(|ti| ti == first) {
+                first
+            } else {
+                "geometry"
+            }
+        }
+        None => "geometry",
+    }
+}
+
+// Convert a vector of optional geometries into an `rsgeo` vector
+// missing geometries are stored as `NULL`
+pub fn geoms_to_rsgeo(x: Vec<Option<Geometry>>) -> Robj {
+    let cls = infer_geom_type(&x);
+
+    let res_vec = x
+        .into_iter()
+        .map(|xi| match xi {
+            Some(g) => Geom::from(g).into_robj(),
+            None => NULL.into_robj(),
+        })
+        .collect::<Vec<Robj>>();
+
+    sfconversions::vctrs::as_rsgeo_vctr(List::from_values(res_vec), cls)
+}
+
 extendr_module! {
     mod utils;
     fn null_pntr;
@@ -0,0 +1,127 @@
+#define R_NO_REMAP
+#include <R.h>
+#include <Rinternals.h>
+#include <setjmp.h>
+#include <stdint.h>
+
+// R errors and other long jumps must never skip over Rust frames. The parts
+// of the wk reader and writer in src/rust/src/io/wkimpl.rs that can jump live
+// here.
+
+// Mirrors `wk_handler_t` in wk's `wk-v1.h`
+typedef struct {
+  int api_version;
+  int dirty;
+  void* handler_data;
+  void (*initialize)(int* dirty, void* handler_data);
+  int (*vector_start)(const void* meta, void* handler_data);
+  int (*feature_start)(const void* meta, R_xlen_t feat_id, void* handler_data);
+  int (*null_feature)(void* handler_data);
+  int (*geometry_start)(const void* meta, uint32_t part_id, void* handler_data);
+  int (*ring_start)(const void* meta, uint32_t size, uint32_t ring_id,
+                    void* handler_data);
+  int (*coord)(const void* meta, const double* coord, uint32_t coord_id,
+               void* handler_data);
+  int (*ring_end)(const void* meta, uint32_t size, uint32_t ring_id,
+                  void* handler_data);
+  int (*geometry_end)(const void* meta, uint32_t part_id, void* handler_data);
+  int (*feature_end)(const void* meta, R_xlen_t feat_id, void* handler_data);
+  SEXP (*vector_end)(const void* meta, void* handler_data);
+  int (*error)(const char* message, void* handler_data);
+  void (*deinitialize)(void* handler_data);
+  void (*finalizer)(void* handler_data);
+} rsgeo_wk_handler_t;
+
+// Defined in src/rust/src/io/wkimpl.rs
+SEXP rsgeo_wk_read(SEXP x, rsgeo_wk_handler_t* handler, SEXP cont,
+                   int* jumped);
+SEXP rsgeo_writer_result(const void* meta, void* handler_data);
+const char* rsgeo_writer_error(void* handler_data);
+
+static void rsgeo_unwind_cleanup(void* jmpbuf, Rboolean jump) {
+  if (jump) {
+    longjmp(*(jmp_buf*)jmpbuf, 1);
+  }
+}
+
+// Run `fun(data)` catching any long jump. When one happens `jumped` is set and
+// the jump is resumed by `rsgeo_wk_handle()` with `R_ContinueUnwind(cont)`
+// once the Rust frames between them have returned.
+SEXP rsgeo_unwind_protect(SEXP (*fun)(void* data), void* data, SEXP cont,
+                          int* jumped) {
+  jmp_buf jmpbuf;
+  *jumped = 0;
+
+  if (setjmp(jmpbuf)) {
+    *jumped = 1;
+    return R_NilValue;
+  }
+
+  return R_UnwindProtect(fun, data, &rsgeo_unwind_cleanup, &jmpbuf, cont);
+}
+
+// Called with .Call() by `wk_handle.rsgeo()`. A long jump from the handler
+// while reading is caught by `rsgeo_unwind_protect()` and resumed here after
+// the handler is deinitialized.
+SEXP rsgeo_wk_handle(SEXP x, SEXP handler_xptr) {
+  if (!Rf_inherits(x, "rsgeo")) {
+    Rf_error("`x` must be of class `rsgeo`");
+  }
+
+  rsgeo_wk_handler_t* handler =
+      (rsgeo_wk_handler_t*)R_ExternalPtrAddr(handler_xptr);
+
+  if (handler == NULL) {
+    Rf_error("`handler` is not a valid wk_handler");
+  }
+
+  if (handler->api_version != 1) {
+    Rf_error("Can't run a wk_handler with api_version '%d'",
+             handler->api_version);
+  }
+
+  if (handler->dirty) {
+    Rf_error("Can't re-use this wk_handler");
+  }
+
+  SEXP cont = PROTECT(R_MakeUnwindCont());
+  int jumped = 0;
+
+  handler->initialize(&handler->dirty, handler->handler_data);
+  SEXP result = rsgeo_wk_read(x, handler, cont, &jumped);
+  if (result != NULL) {
+    PROTECT(result);
+  }
+
+  // deinitialize runs even if reading long jumped
+  handler->deinitialize(handler->handler_data);
+
+  if (jumped) {
+    R_ContinueUnwind(cont);
+  }
+
+  if (result == NULL) {
+    Rf_error("`x` must be a list of geometries");
+  }
+
+  UNPROTECT(2);
+  return result;
+}
+
+void rsgeo_writer_initialize(int* dirty, void* handler_data) {
+  if (*dirty) {
+    Rf_error("Can't re-use this wk_handler");
+  }
+
+  *dirty = 1;
+}
+
+// errors passed to the writer abort reading and are raised here
+SEXP rsgeo_writer_vector_end(const void* meta, void* handler_data) {
+  const char* message = rsgeo_writer_error(handler_data);
+  if (message != NULL) {
+    Rf_error("%s", message);
+  }
+
+  return rsgeo_writer_result(meta, handler_data);
+}